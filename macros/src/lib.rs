@@ -0,0 +1,228 @@
+//! Attribute macros declaring `proto-did` route handlers from plain async
+//! functions, in the fashion of rocket.rs. They are re-exported by `proto-did`
+//! and should be used from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    FnArg, Ident, ItemFn, LitStr, Pat, Path, Token};
+
+/// `<VERB>, "<path template>"`
+struct RouteArgs {
+    verb: Ident,
+    path: LitStr
+}
+
+impl Parse for RouteArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut verb = String::new();
+        let span = input.span();
+
+        // Verbs are not all valid idents (`#DATA`, `WHERE?`), so they are
+        // read token by token up to the comma, or given as a string.
+        if input.peek(LitStr) {
+            verb = input.parse::<LitStr>()?.value();
+        } else {
+            while !input.peek(Token![,]) && !input.is_empty() {
+                verb.push_str(&input.parse::<TokenTree>()?.to_string());
+            }
+        }
+
+        let variant = match verb.as_str() {
+            "PREFLIGHT" => "Preflight",
+            "WHERE?" => "Where",
+            "WHERE!" => "WhereStorage",
+            "#DATA" => "HashData",
+            "DATA" => "Data",
+            _ => return Err(syn::Error::new(
+                span,
+                format!("unknown verb {verb:?}, expected one of PREFLIGHT, \
+                    WHERE?, WHERE!, #DATA or DATA")
+            ))
+        };
+
+        input.parse::<Token![,]>()?;
+
+        let path = input.parse::<LitStr>()?;
+
+        Ok(RouteArgs { verb: Ident::new(variant, span), path })
+    }
+}
+
+/// Names of the parameters declared by a route template, see
+/// `proto_did::req::route::RouteTemplate`.
+fn template_params(path: &LitStr) -> syn::Result<Vec<String>> {
+    let value = path.value();
+    let (segments, query) = match value.split_once('?') {
+        Some((segments, query)) => (segments, Some(query)),
+        None => (value.as_str(), None)
+    };
+
+    if !segments.starts_with('/') {
+        return Err(syn::Error::new(path.span(), "route must start with /"));
+    }
+
+    Ok(segments.split('/')
+        .chain(query)
+        .filter_map(|s| s.strip_prefix('<')?.strip_suffix('>'))
+        .map(|s| s.trim_end_matches("..").to_string())
+        .collect())
+}
+
+/// Declares an async function as a route handler:
+///
+/// ```rust,ignore
+/// #[did_route(#DATA, "/storage/<did>/<resource_id>/set")]
+/// async fn set(
+///     did: String,
+///     resource_id: String,
+///     #[sender] sender: String,
+///     #[body] body: SetRequest
+/// ) -> DIDResponse {
+///     // ...
+/// }
+/// ```
+///
+/// Arguments are extracted from the request as follows:
+/// - arguments named after a template parameter are parsed with `FromStr`,
+/// - `#[body]` arguments are deserialized from the JSON body,
+/// - `#[sender]` arguments are built from the sender DID (`From<String>`),
+/// - other arguments are taken with `FromRequest`, such as `DIDRequest` for
+///   the request itself or `State<T>` for a managed value.
+///
+/// A failed extraction answers `malformed_request` without calling the
+/// function. The function returns any `Responder`: a `DIDResponse`, a
+/// `Json<T>` or a `Result` of those. The route is collected with
+/// `routes![set]`.
+#[proc_macro_attribute]
+pub fn did_route(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as RouteArgs);
+    let mut func = parse_macro_input!(item as ItemFn);
+
+    match expand_route(args, &mut func) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into()
+    }
+}
+
+fn expand_route(
+    args: RouteArgs,
+    func: &mut ItemFn
+) -> syn::Result<TokenStream2> {
+    let params = template_params(&args.path)?;
+
+    if func.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            func.sig.fn_token, "#[did_route] functions must be async"
+        ));
+    }
+
+    let mut extractions = vec![];
+    let mut call_args = vec![];
+
+    for (i, input) in func.sig.inputs.iter_mut().enumerate() {
+        let FnArg::Typed(input) = input else {
+            return Err(syn::Error::new_spanned(
+                input, "#[did_route] functions can't take self"
+            ));
+        };
+
+        let name = match &*input.pat {
+            Pat::Ident(pat) => pat.ident.to_string(),
+            _ => String::new()
+        };
+        let has_attr = |name: &str| {
+            input.attrs.iter().any(|attr| attr.path().is_ident(name))
+        };
+        let is_body = has_attr("body");
+        let is_sender = has_attr("sender");
+        let ty = &input.ty;
+        let var = format_ident!("__arg{}", i);
+
+        input.attrs.retain(|attr| {
+            !attr.path().is_ident("body") && !attr.path().is_ident("sender")
+        });
+
+        let value = if is_body {
+            quote! { req.body.json::<#ty>() }
+        } else if is_sender {
+            quote! { Ok::<#ty, ::proto_did::error::DIDError>(
+                <#ty as ::std::convert::From<String>>::from(req.did.clone())
+            ) }
+        } else if params.contains(&name) {
+            quote! { req.param::<#ty>(#name) }
+        } else {
+            quote! {
+                <#ty as ::proto_did::req::state::FromRequest>::from_request(&req)
+            }
+        };
+
+        extractions.push(quote! {
+            let #var: #ty = match #value {
+                Ok(value) => value,
+                Err(err) => return ::proto_did::req::reqres::DIDResponse::error(
+                    &req, &err
+                )
+            };
+        });
+        call_args.push(var);
+    }
+
+    let vis = &func.vis;
+    let ident = &func.sig.ident;
+    let route_fn = format_ident!("__did_route_{}", ident);
+    let verb = &args.verb;
+    let path = &args.path;
+
+    Ok(quote! {
+        #func
+
+        #[doc(hidden)]
+        #vis fn #route_fn() -> ::proto_did::req::route::DIDRoute {
+            // Named apart from any route function it calls.
+            #[allow(unused_variables)]
+            fn __did_route_inner(
+                req: ::proto_did::req::reqres::DIDRequest
+            ) -> ::std::pin::Pin<::std::boxed::Box<dyn ::std::future::Future<
+                Output = ::proto_did::req::reqres::DIDResponse
+            > + ::std::marker::Send>> {
+                ::std::boxed::Box::pin(async move {
+                    #(#extractions)*
+                    ::proto_did::req::json::Responder::respond_to(
+                        #ident(#(#call_args),*).await, &req
+                    )
+                })
+            }
+
+            ::proto_did::req::route::DIDRoute {
+                verb: ::proto_did::req::verbs::ReqVerb::#verb,
+                path: #path,
+                handler: __did_route_inner
+            }
+        }
+    })
+}
+
+/// Collects functions declared with `#[did_route]` into a
+/// `Vec<DIDRoute>` for `DIDServer::add_routes`: `routes![index, api::get]`.
+#[proc_macro]
+pub fn routes(input: TokenStream) -> TokenStream {
+    let paths = parse_macro_input!(
+        input with Punctuated::<Path, Token![,]>::parse_terminated
+    );
+    let routes = paths.into_iter().map(|mut path| {
+        if let Some(last) = path.segments.last_mut() {
+            last.ident = Ident::new(
+                &format!("__did_route_{}", last.ident),
+                Span::call_site()
+            );
+        }
+        quote! { #path() }
+    });
+
+    quote! { ::std::vec![#(#routes),*] }.into()
+}
@@ -1,14 +1,19 @@
-use std::{collections::HashMap, io::{self, Write}, net::Ipv4Addr, str::FromStr, time::Duration};
+use std::{collections::HashMap, io::{self, Write}, net::IpAddr, str::FromStr};
 
-use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, time::sleep};
-use url::Url;
-
-use crate::{req::{reqres::DIDRequest, verbs::ReqVerb}, tcp::stream::read_expected_size};
+use crate::{
+    client::DIDClient,
+    error::{DIDError, DIDErrorKind, ReqField},
+    DIDServerConfig,
+    req::{
+        reqres::{DIDHeader, DIDRequest, DIDResponse},
+        uri::DIDAddress,
+        verbs::ReqVerb}};
 
 /// This CLI is a `did` small client mainly used for testing. To use the CLI,
 /// call the `start_cli` function in a tokio environment.
 ///
-/// ```rust
+/// ```rust,no_run
+/// # use proto_did::cli::start_cli;
 /// #[tokio::main]
 /// async fn main() {
 ///     start_cli().await;
@@ -20,6 +25,7 @@ pub async fn start_cli() {
 
     ctx.insert("did".into(), "imapotato2".into());
     ctx.insert("ip".into(), "0.0.0.0".into());
+    ctx.insert("port".into(), DIDServerConfig::DEFAULT_PORT.to_string());
     while run {
         let input = get_input();
        
@@ -28,7 +34,7 @@ pub async fn start_cli() {
         }
 
         let args = input.split(" ").collect::<Vec<&str>>();
-        let command = *args.get(0).unwrap();
+        let command = *args.first().unwrap();
 
         run = command != "exit";
         match command {
@@ -36,6 +42,7 @@ pub async fn start_cli() {
                 println!("set \t<key> <value>\t\t\tWill set a runtime value");
                 println!("    \t<did>");
                 println!("    \t<ip>");
+                println!("    \t<port>");
                 println!("get \t<key>        \t\tReads settable properties");
                 println!("send\t<to(ip)> <verb> <path> <body>\t\tSend a DID req");
                 println!("exit\t");
@@ -56,41 +63,61 @@ pub async fn start_cli() {
                 }
             },
             "send" if args.len() == 5 => {
-                let ip = args.get(1).unwrap();
-                let verb = args.get(2).unwrap();
-                let path = args.get(3).unwrap();
-                let body = args.get(4).unwrap();
-
-                let local_ip = ctx.get("ip").unwrap();
-                let local_did = ctx.get("did").unwrap();
-
-                let mut sock = TcpStream::connect(format!("{ip}:5000")).await
-                    .unwrap();
-
-
-                sock.writable().await.unwrap();
-                sock.write_all(DIDRequest {
-                    verb: ReqVerb::from_str(verb).unwrap(),
-                    url: Some(Url::from_str(
-                            &format!("did://{ip}{path}")
-                        ).unwrap()),
-                    ip: Ipv4Addr::from_str(local_ip).unwrap(),
-                    did: local_did.clone(),
-                    body: body.to_string(),
-                    req_size: 0
-                }.to_string().as_bytes()).await.unwrap();
-            
-                println!("waiting for a response");
+                let Ok(port) = ctx.get("port").unwrap().parse::<u16>() else {
+                    println!("Invalid port");
+                    continue;
+                };
 
-                sock.readable().await.unwrap();
-                let response = read_expected_size(&mut sock).await;
-                println!("-> {response}");
+                match send(&ctx, &args[1..], port).await {
+                    Ok(res) => println!(
+                        "-> {} from {} ({}): {}",
+                        res.status,
+                        res.did,
+                        res.ip,
+                        String::from_utf8_lossy(&res.body)
+                    ),
+                    Err(err) => println!("-> {err}")
+                }
             },
             _ => println!("Unknown command: {command}")
         };
     }
 }
 
+/// Sends `<to(ip)> <verb> <path> <body>` to the port `port` of the target.
+/// IPv6 targets may be written with or without brackets.
+async fn send(
+    ctx: &HashMap<String, String>,
+    args: &[&str],
+    port: u16
+) -> Result<DIDResponse, DIDError> {
+    let [ip, verb, path, body] = args else {
+        unreachable!("send takes 4 arguments");
+    };
+    let host = ip.trim_start_matches('[').trim_end_matches(']');
+    let authority = match IpAddr::from_str(host) {
+        Ok(IpAddr::V6(_)) => format!("[{host}]"),
+        _ => host.to_string()
+    };
+    let local_ip = ctx.get("ip").unwrap();
+    let local_ip = IpAddr::from_str(local_ip).map_err(|err| DIDError {
+        kind: DIDErrorKind::MalformedRequest(ReqField::Ip),
+        source: "cli::send".into(),
+        reason: format!("{local_ip:?}: {err}")
+    })?;
+    let header = DIDHeader::new(
+        ReqVerb::from_str(verb)?,
+        Some(DIDAddress::from_str(&format!("did://{authority}{path}"))?),
+        ctx.get("did").unwrap().clone(),
+        local_ip
+    );
+    let req = DIDRequest::from_header(header, body.to_string().into());
+    let client = DIDClient::connect((host, port)).await?;
+
+    println!("waiting for a response");
+    client.send(req).await
+}
+
 fn get_input() -> String {
     let mut buf = String::new();
 
@@ -0,0 +1,361 @@
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    sync::{Arc, Mutex as StdMutex, MutexGuard}};
+use bytes::BytesMut;
+use futures::{
+    stream::{SplitSink, SplitStream},
+    SinkExt,
+    StreamExt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{oneshot, Mutex},
+    task::AbortHandle,
+    time};
+use tokio_util::codec::{Framed, FramedParts};
+use crate::{
+    error::{DIDError, DIDErrorKind},
+    req::reqres::{DIDRequest, DIDResponse},
+    tcp::{
+        codec::{DIDFrame, DIDFrameCodec},
+        config::Heartbeat,
+        http::UPGRADE_PROTOCOL},
+    transport::{BoxTransport, Transport}};
+
+/// Upper bound for the head of an upgrade response.
+const MAX_UPGRADE_HEAD_LEN: usize = 8 * 1024;
+
+/// Client side of a DID connection, which may be any `Transport`. Clones
+/// share the same connection and may each have requests in flight: requests
+/// are tagged with an id (see `DIDRequest::id`, any id set by the caller is
+/// replaced) and every response is routed back to the request it answers.
+/// Responses without id, from servers ignoring the extension, are matched to
+/// the oldest request waiting.
+///
+/// The connection is read by a background task, so clients are created
+/// within a Tokio runtime.
+///
+/// ```rust,no_run
+/// # use proto_did::{client::DIDClient, req::reqres::DIDRequest};
+/// # async fn run(req: DIDRequest) -> Result<(), proto_did::error::DIDError> {
+/// let client = DIDClient::connect("127.0.0.1:5173").await?;
+/// let res = client.send(req).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DIDClient {
+    inner: Arc<ClientInner>
+}
+
+type Responder = oneshot::Sender<Result<DIDResponse, DIDError>>;
+
+struct ClientInner {
+    sink: Mutex<SplitSink<Framed<BoxTransport, DIDFrameCodec>, DIDFrame>>,
+    pending: Arc<StdMutex<Pending>>,
+    reader: AbortHandle
+}
+
+impl ClientInner {
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        lock(&self.pending)
+    }
+
+    /// Stops reading the connection, failing every request with `err`.
+    fn close(&self, err: DIDError) {
+        self.reader.abort();
+        self.pending().close(err);
+    }
+}
+
+impl Drop for ClientInner {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Requests waiting for their response, by id. Ids only grow, so the first
+/// entry is the oldest request.
+#[derive(Default)]
+struct Pending {
+    waiting: BTreeMap<u64, Responder>,
+    next_id: u64,
+    /// Why the connection stopped being read, refusing further requests.
+    closed: Option<DIDError>
+}
+
+impl Pending {
+    fn register(&mut self, responder: Responder) -> Result<u64, DIDError> {
+        if let Some(err) = &self.closed {
+            return Err(err.clone());
+        }
+
+        let id = self.next_id;
+
+        self.next_id += 1;
+        self.waiting.insert(id, responder);
+        Ok(id)
+    }
+
+    fn take(&mut self, id: Option<u64>) -> Option<Responder> {
+        match id {
+            Some(id) => self.waiting.remove(&id),
+            None => self.waiting.pop_first().map(|(_, responder)| responder)
+        }
+    }
+
+    /// Fails every request waiting and the ones sent afterwards with `err`.
+    fn close(&mut self, err: DIDError) {
+        for (_, responder) in std::mem::take(&mut self.waiting) {
+            let _ = responder.send(Err(err.clone()));
+        }
+        self.closed = Some(err);
+    }
+}
+
+/// Forgets a request when its `send` future ends, so a cancelled request
+/// isn't answered by the next untagged response (see `Pending::take`). It is
+/// already gone if its response was received.
+struct Waiting<'a> {
+    pending: &'a StdMutex<Pending>,
+    id: u64
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        lock(self.pending).waiting.remove(&self.id);
+    }
+}
+
+fn lock(pending: &StdMutex<Pending>) -> MutexGuard<'_, Pending> {
+    pending.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn closed_error(reason: &str) -> DIDError {
+    DIDError {
+        kind: DIDErrorKind::TcpConnectionClosed,
+        source: "DIDClient::send".into(),
+        reason: reason.into()
+    }
+}
+
+/// Routes the responses read on `stream` to the requests waiting for them,
+/// until the connection closes.
+async fn read_responses(
+    mut stream: SplitStream<Framed<BoxTransport, DIDFrameCodec>>,
+    pending: Arc<StdMutex<Pending>>
+) {
+    let err = loop {
+        let frame = match stream.next().await {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => break err,
+            None => break closed_error("connection closed before the response")
+        };
+        // A response that can't be read can't be routed either.
+        let res = match DIDResponse::try_from(frame) {
+            Ok(res) => res,
+            Err(err) => break err
+        };
+
+        match lock(&pending).take(res.id) {
+            Some(responder) => {
+                let _ = responder.send(Ok(res));
+            },
+            None => warn!("Response to an unknown request {:?}", res.id)
+        }
+    };
+
+    lock(&pending).close(err);
+}
+
+impl DIDClient {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self, DIDError> {
+        let sock = TcpStream::connect(addr).await?;
+
+        Ok(DIDClient::from_stream(sock))
+    }
+
+    /// Connects to a server listening on the Unix domain socket `path`.
+    #[cfg(unix)]
+    pub async fn connect_unix(
+        path: impl AsRef<std::path::Path>
+    ) -> Result<Self, DIDError> {
+        let sock = tokio::net::UnixStream::connect(path).await?;
+
+        Ok(DIDClient::from_transport(sock))
+    }
+
+    pub fn from_stream(sock: TcpStream) -> Self {
+        DIDClient::from_transport(sock)
+    }
+
+    /// Uses an established connection, such as the stream returned by
+    /// `ServerHandle::duplex`.
+    pub fn from_transport(io: impl Transport) -> Self {
+        let io = Box::new(io) as BoxTransport;
+
+        DIDClient::from_framed(Framed::new(io, DIDFrameCodec::new()))
+    }
+
+    fn from_framed(stream: Framed<BoxTransport, DIDFrameCodec>) -> Self {
+        let (sink, stream) = stream.split();
+        let pending = Arc::new(StdMutex::new(Pending::default()));
+        let reader = tokio::spawn(read_responses(stream, pending.clone()));
+
+        DIDClient {
+            inner: Arc::new(ClientInner {
+                sink: Mutex::new(sink),
+                pending,
+                reader: reader.abort_handle()
+            })
+        }
+    }
+
+    /// Connects to `addr` over HTTP and switches the connection to `did://`
+    /// framing, see `upgrade`.
+    pub async fn connect_upgrade(
+        addr: impl ToSocketAddrs,
+        host: &str
+    ) -> Result<Self, DIDError> {
+        let sock = TcpStream::connect(addr).await?;
+
+        DIDClient::upgrade(sock, host).await
+    }
+
+    /// Switches an HTTP connection to `did://` framing with an
+    /// `Upgrade: did` request, as done with DNS DIDs once reached over HTTPS
+    /// (the README expects a `PREFLIGHT` as first request). `host` is the
+    /// HTTP host of the server, such as `DIDAddress::dns_host`.
+    ///
+    /// TLS is not handled here: `sock` is expected to be the plain side of
+    /// the connection.
+    pub async fn upgrade(
+        sock: impl Transport,
+        host: &str
+    ) -> Result<Self, DIDError> {
+        let error = |reason: String| DIDError {
+            kind: DIDErrorKind::TcpFailure,
+            source: "DIDClient::upgrade".into(),
+            reason
+        };
+        let mut sock = Box::new(sock) as BoxTransport;
+        let req = format!(
+            "GET / HTTP/1.1\r\nHost: {host}\r\nConnection: Upgrade\r\n\
+            Upgrade: {UPGRADE_PROTOCOL}\r\n\r\n"
+        );
+
+        sock.write_all(req.as_bytes()).await?;
+
+        let mut buf = BytesMut::new();
+        let head_len = loop {
+            if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end;
+            }
+            if buf.len() > MAX_UPGRADE_HEAD_LEN {
+                return Err(error("upgrade response too long".into()));
+            }
+            if sock.read_buf(&mut buf).await? == 0 {
+                return Err(DIDError {
+                    kind: DIDErrorKind::TcpConnectionClosed,
+                    source: "DIDClient::upgrade".into(),
+                    reason: "connection closed during the upgrade".into()
+                });
+            }
+        };
+
+        let head = buf.split_to(head_len + 4);
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+
+        if !status_line.starts_with("HTTP/1.1 101 ") {
+            return Err(error(format!("upgrade refused: {status_line}")));
+        }
+
+        // The server may already have written DID frames after its response.
+        let mut parts = FramedParts::new::<DIDFrame>(sock, DIDFrameCodec::new());
+
+        parts.read_buf = buf;
+        Ok(DIDClient::from_framed(Framed::from_parts(parts)))
+    }
+
+    /// Sends `req` and waits for its response. Several requests may be
+    /// sent at once, from clones of the client.
+    pub async fn send(
+        &self,
+        mut req: DIDRequest
+    ) -> Result<DIDResponse, DIDError> {
+        let (responder, response) = oneshot::channel();
+        let id = self.inner.pending().register(responder)?;
+        // Dropped once answered, failed or cancelled.
+        let _waiting = Waiting { pending: &self.inner.pending, id };
+
+        req.id = Some(id);
+        self.inner.sink.lock().await.send(req.to_frame()).await?;
+
+        response.await.unwrap_or_else(|_| {
+            Err(closed_error("connection closed before the response"))
+        })
+    }
+
+    /// Sends a `PING` from `did` and `ip` every `heartbeat.period()` while
+    /// the client is alive, so the connection isn't dropped as idle by NATs,
+    /// firewalls or the server (see `DIDServerConfig::heartbeat`). Once
+    /// `heartbeat.max_missed` pings in a row go unanswered, the client is
+    /// closed and every request fails as `tcp_connection_closed`.
+    pub fn start_heartbeat(&self, did: &str, ip: IpAddr, heartbeat: Heartbeat) {
+        let inner = Arc::downgrade(&self.inner);
+        let ping = DIDRequest::ping(did, ip);
+
+        tokio::spawn(async move {
+            let mut interval = time::interval(heartbeat.period());
+            let mut missed = 0;
+
+            // The first tick completes right away.
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+
+                let Some(inner) = inner.upgrade() else {
+                    return;
+                };
+                let client = DIDClient { inner };
+                // A ping timing out is forgotten with its `send`, a late
+                // pong can't answer the next request.
+                let pong = client.send(ping.clone());
+
+                match time::timeout(heartbeat.period(), pong).await {
+                    Ok(Ok(_)) => missed = 0,
+                    Ok(Err(_)) => return,
+                    Err(_) => missed += 1
+                }
+                if missed >= heartbeat.max_missed.max(1) {
+                    return client.inner.close(closed_error(&format!(
+                        "{missed} heartbeats missed"
+                    )));
+                }
+            }
+        });
+    }
+}
+
+#[cfg(feature = "tower")]
+impl tower_service::Service<DIDRequest> for DIDClient {
+    type Response = DIDResponse;
+    type Error = DIDError;
+    type Future = futures::future::BoxFuture<'static, Result<DIDResponse, DIDError>>;
+
+    fn poll_ready(
+        &mut self,
+        _: &mut std::task::Context<'_>
+    ) -> std::task::Poll<Result<(), DIDError>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: DIDRequest) -> Self::Future {
+        let client = self.clone();
+
+        Box::pin(async move { client.send(req).await })
+    }
+}
@@ -1,19 +1,165 @@
-use std::{error::Error, fmt::{Debug, Display}};
+use std::{error::Error, fmt::{Debug, Display}, io, str::FromStr};
 
+/// Header or body part a `malformed_request` error refers to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ReqField {
+    Verb,
+    Url,
+    Did,
+    Ip,
+    Size,
+    /// The optional `id=<ID>` request id, see `DIDRequest::id`.
+    Id,
+    /// The `,` between header fields or the blank line after the header.
+    Separator,
+    /// The header line as a whole (encoding, length).
+    Header,
+    Body
+}
+
+/// Error kinds defined by the protocol, see "Error conditions, timeouts, and
+/// failures" in the README.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DIDErrorKind {
+    TcpFailure,
+    TcpConnectionClosed,
+    DidPreflightEcdhTampering,
+    MalformedRequest(ReqField),
+    DidNoPublicSessionKey,
+    DidNoPreflightResponse,
+    DidNotFound,
+    DidTimeout,
+    DidCheckFailure,
+    DidNoPreflight,
+    DidDnsNotFound,
+    DidDnsTimeout,
+    DidLookupTimeout,
+    /// The server failed to answer a valid request, such as a handler
+    /// taking a value that isn't managed or a response body failing to
+    /// serialize.
+    ServerError
+}
+
+#[derive(Clone)]
 pub struct DIDError {
+    pub kind: DIDErrorKind,
     pub source: String,
     pub reason: String
 }
 
+impl Display for ReqField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let field = match self {
+            Self::Verb => "verb",
+            Self::Url => "url",
+            Self::Did => "did",
+            Self::Ip => "ip",
+            Self::Size => "size",
+            Self::Id => "id",
+            Self::Separator => "separator",
+            Self::Header => "header",
+            Self::Body => "body"
+        };
+
+        write!(f, "{}", field)
+    }
+}
+
+impl Display for DIDErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Self::TcpFailure => "tcp_failure",
+            Self::TcpConnectionClosed => "tcp_connection_closed",
+            Self::DidPreflightEcdhTampering => "did_preflight_ecdh_tampering",
+            Self::MalformedRequest(_) => "malformed_request",
+            Self::DidNoPublicSessionKey => "did_no_public_session_key",
+            Self::DidNoPreflightResponse => "did_no_preflight_response",
+            Self::DidNotFound => "did_not_found",
+            Self::DidTimeout => "did_timeout",
+            Self::DidCheckFailure => "did_check_failure",
+            Self::DidNoPreflight => "did_no_preflight",
+            Self::DidDnsNotFound => "did_dns_not_found",
+            Self::DidDnsTimeout => "did_dns_timeout",
+            Self::DidLookupTimeout => "did_lookup_timeout",
+            Self::ServerError => "server_error"
+        };
+
+        write!(f, "{}", kind)
+    }
+}
+
+impl FromStr for ReqField {
+    type Err = DIDError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verb" => Ok(Self::Verb),
+            "url" => Ok(Self::Url),
+            "did" => Ok(Self::Did),
+            "ip" => Ok(Self::Ip),
+            "size" => Ok(Self::Size),
+            "id" => Ok(Self::Id),
+            "separator" => Ok(Self::Separator),
+            "header" => Ok(Self::Header),
+            "body" => Ok(Self::Body),
+            _ => Err(DIDError {
+                kind: DIDErrorKind::MalformedRequest(ReqField::Header),
+                source: "ReqField::from_str".into(),
+                reason: format!("unknown field {s:?}")
+            })
+        }
+    }
+}
+
+impl FromStr for DIDErrorKind {
+    type Err = DIDError;
+
+    /// Parses the kind names of the README. `malformed_request` may be
+    /// followed by `:<field>`, see `DIDStatus`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(field) = s.strip_prefix("malformed_request:") {
+            return Ok(Self::MalformedRequest(ReqField::from_str(field)?));
+        }
+
+        match s {
+            "malformed_request" => Ok(Self::MalformedRequest(ReqField::Header)),
+            "tcp_failure" => Ok(Self::TcpFailure),
+            "tcp_connection_closed" => Ok(Self::TcpConnectionClosed),
+            "did_preflight_ecdh_tampering" => Ok(Self::DidPreflightEcdhTampering),
+            "did_no_public_session_key" => Ok(Self::DidNoPublicSessionKey),
+            "did_no_preflight_response" => Ok(Self::DidNoPreflightResponse),
+            "did_not_found" => Ok(Self::DidNotFound),
+            "did_timeout" => Ok(Self::DidTimeout),
+            "did_check_failure" => Ok(Self::DidCheckFailure),
+            "did_no_preflight" => Ok(Self::DidNoPreflight),
+            "did_dns_not_found" => Ok(Self::DidDnsNotFound),
+            "did_dns_timeout" => Ok(Self::DidDnsTimeout),
+            "did_lookup_timeout" => Ok(Self::DidLookupTimeout),
+            "server_error" => Ok(Self::ServerError),
+            _ => Err(DIDError {
+                kind: DIDErrorKind::MalformedRequest(ReqField::Header),
+                source: "DIDErrorKind::from_str".into(),
+                reason: format!("unknown error kind {s:?}")
+            })
+        }
+    }
+}
+
 impl Display for DIDError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}: {}", self.source, self.reason)
+        match self.kind {
+            DIDErrorKind::MalformedRequest(field) => write!(
+                f, "{} ({}) in {}: {}", self.kind, field, self.source, self.reason
+            ),
+            kind => write!(f, "{} in {}: {}", kind, self.source, self.reason)
+        }
     }
 }
 
 impl Debug for DIDError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DIDError")
+            .field("kind", &self.kind)
             .field("source", &self.source)
             .field("reason", &self.reason)
             .finish()
@@ -21,3 +167,21 @@ impl Debug for DIDError {
 }
 
 impl Error for DIDError {}
+
+impl From<io::Error> for DIDError {
+    fn from(err: io::Error) -> Self {
+        let kind = match err.kind() {
+            io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe => DIDErrorKind::TcpConnectionClosed,
+            _ => DIDErrorKind::TcpFailure
+        };
+
+        DIDError {
+            kind,
+            source: "io".into(),
+            reason: err.to_string()
+        }
+    }
+}
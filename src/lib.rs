@@ -1,80 +1,265 @@
 #[macro_use] extern crate log;
+extern crate self as proto_did;
 
-use std::collections::HashMap;
+use std::io;
 use identity::DIDIdentity;
-use req::{reqres::{DIDRequest, DIDResponse}, uri::DIDUri, verbs::ReqVerb};
-use tcp::listener::tcp_server;
+use req::{
+    handler::Handler,
+    layer::Layer,
+    route::DIDRoute,
+    router::Router,
+    verbs::ReqVerb};
+use tcp::listener::{bind, spawn_server, ServerContext};
+use transport::Acceptor;
 
 pub mod cli;
+pub mod client;
 mod tcp;
-mod req;
-mod error;
+pub mod req;
+pub mod error;
 mod identity;
+pub mod transport;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+pub use proto_did_macros::{did_route, routes};
+pub use tcp::{
+    codec::{DIDFrame, DIDFrameCodec},
+    config::{DIDServerConfig, Heartbeat},
+    listener::{BindAddr, ServerHandle}};
 
 /// Contains the configuration of the whole server.
-pub struct DIDServer<'s> {
-    pub port: usize,
-    pub routes: HashMap<
-        DIDUri, fn(DIDRequest) -> dyn Future<Output = DIDResponse<'s>>>,
+pub struct DIDServer {
+    pub config: DIDServerConfig,
+    pub routes: Router,
     pub identity: DIDIdentity,
     /// Determines if the server is allowed to use HTTP for DID DNS reach out
     /// and DID to device communication.
     pub http_enabled: bool,
-    pub did_enabled: bool
+    pub did_enabled: bool,
+    /// Activity of the server, see the `metrics` module.
+    #[cfg(feature = "metrics")]
+    pub metrics: metrics::Metrics,
+    #[cfg(feature = "metrics")]
+    metrics_path: Option<String>
 }
 
-impl<'s> DIDServer<'s> {
+impl DIDServer {
     pub fn build() -> Self {
-        env_logger::init();
+        // Several servers may run in the same process.
+        let _ = env_logger::try_init();
+
         DIDServer {
-            port: 5173,
-            routes: HashMap::new(),
+            config: DIDServerConfig::new(),
+            routes: Router::new(),
             identity: DIDIdentity {
                 did: "imapotato".to_string()
             },
             http_enabled: true,
-            did_enabled: true
+            did_enabled: true,
+            #[cfg(feature = "metrics")]
+            metrics: metrics::Metrics::new(),
+            #[cfg(feature = "metrics")]
+            metrics_path: None
         }
     }
 
 
-    pub fn set_port(&mut self, port: usize) -> &mut Self {
-        self.port = port;
+    /// Replaces the listener and connection options, see `DIDServerConfig`.
+    pub fn set_config(&mut self, config: DIDServerConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    pub fn set_port(&mut self, port: u16) -> &mut Self {
+        self.config.set_port(port);
+        self
+    }
+
+    /// Replaces the addresses the server listens on. Use `BindAddr::DualStack`
+    /// to accept both IPv4 and IPv6 peers on a single socket.
+    pub fn set_bind_addrs(&mut self, addrs: &[BindAddr]) -> &mut Self {
+        self.config.set_bind_addrs(addrs);
+        self
+    }
+
+    /// Registers `handler` for the requests with `verb` whose URL path matches
+    /// the template `path`, such as `/ar/score/<did>` (see `RouteTemplate`).
+    /// See `Handler` for the accepted handler signatures.
+    ///
+    /// `#DATA` routes are refused, see `extend_protocol`.
+    pub fn add_route(
+        &mut self,
+        verb: ReqVerb,
+        path: &str,
+        handler: impl Handler
+    ) -> &mut Self {
+        self.routes.add_route(verb, path, handler);
+        self
+    }
+
+    /// Registers `handler` as a `#DATA` protocol extension on `path`. `#DATA`
+    /// is reserved to the protocol, see `Router`.
+    pub fn extend_protocol(
+        &mut self,
+        path: &str,
+        handler: impl Handler
+    ) -> &mut Self {
+        self.routes.extend_protocol(path, handler);
+        self
+    }
+
+    /// Serves the `#DATA` protocol endpoint `path` with `handler`, see
+    /// `Router::set_protocol_handler`.
+    pub fn set_protocol_handler(
+        &mut self,
+        path: &str,
+        handler: impl Handler
+    ) -> &mut Self {
+        self.routes.set_protocol_handler(path, handler);
+        self
+    }
+
+    /// Registers routes declared with `#[did_route]`:
+    /// `server.add_routes(routes![index, storage::set])`.
+    pub fn add_routes(&mut self, routes: Vec<DIDRoute>) -> &mut Self {
+        self.routes.add_routes(routes);
+        self
+    }
+
+    /// Adds a layer run around every request, see `Layer`. Layers run in the
+    /// order they are added.
+    pub fn add_layer(&mut self, layer: impl Layer) -> &mut Self {
+        self.routes.add_layer(layer);
         self
     }
 
-    pub fn add_route<F>(
+    /// Adds a layer run only around the route registered for `verb` and
+    /// `path`, after the global layers.
+    pub fn add_route_layer(
         &mut self,
         verb: ReqVerb,
         path: &str,
-        cb: fn(DIDRequest) -> dyn Future<Output = DIDResponse<'s>>
+        layer: impl Layer
     ) -> &mut Self {
-        self.routes.insert(DIDUri {
-            url: None,
-            path: Some(path.to_string()),
-            verb
-        }, cb);
+        self.routes.add_route_layer(verb, path, layer);
+        self
+    }
+
+    /// Shares `value` with every handler and layer across connections. It is
+    /// taken with the `State<T>` extractor or `DIDRequest::state`:
+    /// `server.manage(ArTable::default())`.
+    pub fn manage<T: Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
+        self.routes.manage(value);
+        self
+    }
+
+    /// Serves `self.metrics` to HTTP `GET` requests on `path`, such as
+    /// `/metrics`, on the DID port. Requires `http_enabled`.
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(&mut self, path: &str) -> &mut Self {
+        self.metrics_path = Some(path.to_string());
         self
     }
 
-    /// Launche a socket listener on `self.port`. This
-    /// function must be called after initializing everything you need in your
-    /// app.
+    /// Launche a socket listener on every bind address of `self.config` and
+    /// runs until the process stops. See `start` to keep a handle on the
+    /// server. This function must be called after initializing everything you
+    /// need in your app.
     ///
     /// Usage:
-    /// ```rust
+    /// ```rust,no_run
+    ///  # use proto_did::{DIDServer, req::{reqres::*, verbs::ReqVerb}};
+    ///  async fn index(req: DIDRequest) -> DIDResponse {
+    ///     DIDResponse::ok(&req, "hello")
+    ///  }
+    ///
     ///  #[tokio::main]
     ///  async fn main() {
     ///     DIDServer::build()
     ///         .set_port(3000)
-    ///         .add_route(ReqVeb::Where, "/", index)
+    ///         .add_route(ReqVerb::Data, "/", index)
     ///         .launch()
     ///         .await
     ///  }
     /// ```
     pub async fn launch(&self) {
-        tcp_server(self.port, self.identity.clone())
+        self.start()
+            .expect("TcpServer bind error!")
+            .wait()
             .await
             .expect("TcpServer error!");
     }
+
+    /// Same as `launch`, shutting the server down gracefully once `signal`
+    /// completes, such as `tokio::signal::ctrl_c()`.
+    pub async fn launch_until(&self, signal: impl Future) {
+        let handle = self.start().expect("TcpServer bind error!");
+
+        signal.await;
+        handle.shutdown().await.expect("TcpServer error!");
+    }
+
+    /// Same as `launch`, on a listener bound by the caller: a socket-activated
+    /// listener, one bound on an ephemeral port or a `UnixListener` for local
+    /// IPC. The bind options of `self.config` are not applied to it, its
+    /// connection options are.
+    pub async fn launch_with_listener(&self, listener: impl Acceptor) {
+        self.start_with_listener(listener)
+            .expect("TcpServer error!")
+            .wait()
+            .await
+            .expect("TcpServer error!");
+    }
+
+    /// Binds the server and runs it in the background, returning a handle to
+    /// get its address and shut it down:
+    ///
+    /// ```rust,no_run
+    /// # use proto_did::DIDServer;
+    /// # async fn run() -> std::io::Result<()> {
+    /// let handle = DIDServer::build().set_port(0).start()?;
+    ///
+    /// println!("listening on {}", handle.local_addr());
+    /// handle.shutdown().await
+    /// # }
+    /// ```
+    pub fn start(&self) -> io::Result<ServerHandle> {
+        let listeners = bind(&self.config)?.into_iter()
+            .map(|listener| Box::new(listener) as Box<dyn Acceptor>)
+            .collect();
+
+        Ok(self.serve(listeners))
+    }
+
+    /// Same as `start`, on a listener bound by the caller.
+    pub fn start_with_listener(
+        &self,
+        listener: impl Acceptor
+    ) -> io::Result<ServerHandle> {
+        Ok(self.serve(vec![Box::new(listener)]))
+    }
+
+    /// Runs the server in the background without listening anywhere: it
+    /// only serves the connections given to `ServerHandle::serve`, such as
+    /// in-memory ones from `ServerHandle::duplex`.
+    pub fn start_in_memory(&self) -> ServerHandle {
+        self.serve(Vec::new())
+    }
+
+    fn serve(&self, listeners: Vec<Box<dyn Acceptor>>) -> ServerHandle {
+        let ctx = ServerContext::new(
+            self.identity.clone(),
+            self.routes.clone(),
+            self.config.clone(),
+            self.http_enabled,
+            self.did_enabled
+        );
+        #[cfg(feature = "metrics")]
+        let ctx = ctx.with_metrics(
+            self.metrics.clone(), self.metrics_path.clone()
+        );
+
+        spawn_server(listeners, ctx)
+    }
 }
@@ -0,0 +1,152 @@
+//! Prometheus metrics of a `DIDServer`, with the `metrics` feature.
+//!
+//! Every server records its activity in `DIDServer::metrics`. They are
+//! exposed with `Metrics::gather`, or served over HTTP on the DID port with
+//! `DIDServer::serve_metrics`. Applications register their own metrics in
+//! `Metrics::registry`.
+
+use std::time::Duration;
+use prometheus::{
+    Encoder,
+    HistogramOpts,
+    HistogramVec,
+    IntCounter,
+    IntCounterVec,
+    IntGauge,
+    Opts,
+    Registry,
+    TextEncoder};
+use crate::req::{
+    reqres::{DIDRequest, DIDStatus},
+    verbs::ReqVerb};
+
+/// Metrics of a node. Clones share the same metrics and registry.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    registry: Registry,
+    /// Connections accepted, every transport included.
+    pub connections: IntCounter,
+    /// Open sessions, see "Session caching" in the README.
+    pub sessions: IntGauge,
+    /// Sessions closed after `session_ttl` without activity.
+    pub sessions_expired: IntCounter,
+    /// Connections closed after missing their heartbeats.
+    pub heartbeat_evictions: IntCounter,
+    /// Dispatched requests, by kind (see `request_kind`) and status.
+    pub requests: IntCounterVec,
+    /// Time spent dispatching requests, by kind.
+    pub request_duration: HistogramVec,
+    /// Entries of the AR table, set by the application maintaining it.
+    pub ar_table_size: IntGauge
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let metrics = Metrics {
+            connections: IntCounter::new(
+                "did_connections_total", "Connections accepted"
+            ).unwrap(),
+            sessions: IntGauge::new(
+                "did_sessions", "Open sessions in the session cache"
+            ).unwrap(),
+            sessions_expired: IntCounter::new(
+                "did_sessions_expired_total", "Sessions closed as inactive"
+            ).unwrap(),
+            heartbeat_evictions: IntCounter::new(
+                "did_heartbeat_evictions_total",
+                "Connections closed after missing heartbeats"
+            ).unwrap(),
+            requests: IntCounterVec::new(
+                Opts::new("did_requests_total", "Dispatched requests"),
+                &["kind", "status"]
+            ).unwrap(),
+            request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "did_request_duration_seconds", "Request dispatch time"
+                ),
+                &["kind"]
+            ).unwrap(),
+            ar_table_size: IntGauge::new(
+                "did_ar_table_size", "Entries of the AR table"
+            ).unwrap(),
+            registry
+        };
+
+        for collector in metrics.collectors() {
+            metrics.registry.register(collector)
+                .expect("metric registered twice");
+        }
+        metrics
+    }
+
+    fn collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![
+            Box::new(self.connections.clone()),
+            Box::new(self.sessions.clone()),
+            Box::new(self.sessions_expired.clone()),
+            Box::new(self.heartbeat_evictions.clone()),
+            Box::new(self.requests.clone()),
+            Box::new(self.request_duration.clone()),
+            Box::new(self.ar_table_size.clone())
+        ]
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Every metric of the registry, in the Prometheus text format.
+    pub fn gather(&self) -> String {
+        let mut buf = Vec::new();
+
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("text encoding of metrics");
+        String::from_utf8(buf).expect("metrics are UTF-8")
+    }
+
+    /// Records a request of `kind` answered with `status` in `elapsed`.
+    pub(crate) fn observe(
+        &self,
+        kind: &str,
+        status: &DIDStatus,
+        elapsed: Duration
+    ) {
+        self.requests
+            .with_label_values(&[kind, &status.to_string()])
+            .inc();
+        self.request_duration
+            .with_label_values(&[kind])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// `kind` label of a request: `preflight`, `lookup` for `WHERE?` and
+/// `WHERE!`, `ar` and `storage` for the `#DATA` endpoints of the protocol,
+/// `protocol` for the other `#DATA` requests, `data`, and `heartbeat` for the
+/// `PING`s answered by the server itself.
+pub fn request_kind(req: &DIDRequest) -> &'static str {
+    match req.verb {
+        ReqVerb::Preflight => "preflight",
+        ReqVerb::Where | ReqVerb::WhereStorage => "lookup",
+        ReqVerb::HashData => {
+            let namespace = req.url.as_ref()
+                .and_then(|url| url.path.split('/').nth(1));
+
+            match namespace {
+                Some("ar") => "ar",
+                Some("storage") => "storage",
+                _ => "protocol"
+            }
+        },
+        ReqVerb::Data => "data",
+        ReqVerb::Ping => "heartbeat"
+    }
+}
@@ -0,0 +1,303 @@
+//! Bodies of the protocol requests and responses, as documented in the
+//! README. Timestamps are UNIX epochs in seconds, sizes, speeds and prices are
+//! kept as written on the wire (`12Go`, `10Mb/s`, `0.000000007btc/Go`).
+//!
+//! Every type refuses unknown fields, so `Json<T>` answers
+//! `malformed_request:body` for unknown as well as missing fields.
+
+use std::net::IpAddr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// `WHERE?` request body, a target lookup relayed through neighbors.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WhereQuery {
+    pub created_at: u64,
+    pub request_id: String,
+    pub requested_by: String,
+    pub requested_by_ip: IpAddr,
+    /// Size of the neighbor tree since the request, aborted past 30.
+    pub depth: u32
+}
+
+/// `WHERE?` response body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WhereResponse {
+    pub request_id: String,
+    pub from_dwn: String,
+    pub from_dwn_ip: IpAddr,
+    pub requested_address_found: bool,
+    pub requested_address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_address_ip: Option<IpAddr>
+}
+
+/// Event of a `ScoringLog`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoringEvent {
+    pub event_name: String,
+    pub timestamp: u64
+}
+
+/// `#DATA /ar/score/<did>` response body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoringLog {
+    pub for_target: String,
+    pub scoring_log: Vec<ScoringEvent>
+}
+
+/// `#DATA /ar/get` request body, answered with a `Vec<ArEntry>`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArSliceRequest {
+    pub get_entries: usize
+}
+
+/// Entry of an AR table, also used for replicas and storage clients.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArEntry {
+    pub did: String,
+    pub ip: IpAddr,
+    pub last_checked: u64,
+    pub alive: bool,
+    pub available_storage: String,
+    pub availability: f64,
+    pub data_consistency: f64,
+    pub avg_network_speed: String,
+    pub current_price: String
+}
+
+/// Storage provider filters of a `StorageLookup`, all optional.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageFilters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_availability: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_data_consistency: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_available_storage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_avg_network_speed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_price: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_price: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_replications: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored_data_for_at_least: Option<u32>
+}
+
+/// `WHERE!` request body, a storage lookup relayed through neighbors.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageLookup {
+    pub created_at: u64,
+    pub filter_by: StorageFilters,
+    pub requested_by: String,
+    pub requested_by_ip: IpAddr,
+    pub depth: u32
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageType {
+    Main,
+    Replicated
+}
+
+/// `WHERE!` response body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageLookupResponse {
+    pub from_dwn: String,
+    pub from_dwn_ip: IpAddr,
+    pub filters: StorageFilters,
+    pub requested_address_found: bool,
+    pub requested_address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_address_ip: Option<IpAddr>,
+    pub available_storage: String,
+    pub availability: f64,
+    pub data_consistency: f64,
+    pub avg_network_speed: String,
+    pub avg_price: String,
+    pub current_price: String,
+    pub replicated_on: Vec<String>,
+    pub stored_data_for: Vec<String>,
+    pub storage_type: StorageType,
+    pub location: String,
+    pub bln_address: String
+}
+
+/// `never`, for providers a DID never stored data with.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Never {
+    Never
+}
+
+/// Start of a storage relationship, an epoch or `"never"`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum StorageStart {
+    At(u64),
+    Never(Never)
+}
+
+/// `#DATA /storage/stats?<provider>` response body. Only `for_provider` and
+/// `storage_started_at` are given when the DID never dealt with the provider.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageStats {
+    pub for_provider: String,
+    pub storage_started_at: StorageStart,
+    /// `-1` while the storage is ongoing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_ended_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_consistency: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub availability: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_network_speed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_price: Option<String>
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMethod {
+    Raw,
+    Kv
+}
+
+/// `#DATA /storage/occupy` request body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OccupyRequest {
+    pub reserve_space: String,
+    pub storage_method: StorageMethod,
+    pub with_replications: u32
+}
+
+/// `#DATA /storage/occupy` response body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OccupyResponse {
+    pub asked_space: String,
+    pub with_storage_method: StorageMethod,
+    pub with_replications: u32,
+    pub available_space: String,
+    pub max_replications: u32,
+    pub approved: bool,
+    /// Reserves the space for 20 seconds, given when approved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bln_address: Option<String>,
+    pub replicas: Vec<ArEntry>,
+    pub stored_data_for: Vec<ArEntry>
+}
+
+/// `#DATA /storage/create_resource_with_approval_id` request body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateResourceRequest {
+    pub store_with_approval_id: String,
+    /// Data encrypted using the DID.
+    pub payload: String
+}
+
+/// `#DATA /storage/create_resource_with_approval_id` response body. `id` holds
+/// the encrypted identifiers of the provider and its replicas when stored,
+/// `reason` why it was not otherwise.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateResourceResponse {
+    pub stored: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>
+}
+
+/// Resource as kept by a storage provider, returned by `#DATA
+/// /storage/<did>/<resource_id>` and `.../rm`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoredResource {
+    pub id: String,
+    pub owned_by: String,
+    pub storage_started_at: u64,
+    pub last_payment: u64,
+    pub allocated_size: String,
+    pub occupied_size: String,
+    pub replicas: Vec<String>,
+    /// Encrypted dataset, a JSON object for KV databases.
+    pub dataset: Value
+}
+
+/// `#DATA /storage/<did>/<resource_id>/set` request body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetRequest {
+    /// Encrypted data, a JSON object for KV databases.
+    pub payload: Value
+}
+
+/// `#DATA /storage/<did>/<resource_id>/set` response body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetResponse {
+    pub approved: bool
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KvMethod {
+    Get,
+    Set,
+    Delete
+}
+
+/// `#DATA /storage/<did>/<resource_id>/kv` request body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct KvOp {
+    /// Encrypted keys path, `encryptedKey.encryptedChild.encryptedLeaf`.
+    pub target: String,
+    pub method: KvMethod,
+    /// Value written by `set`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_value: Option<Value>
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KvError {
+    NotFound,
+    NotEnoughSpace
+}
+
+/// `#DATA /storage/<did>/<resource_id>/kv` response body.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct KvResponse {
+    pub for_target: String,
+    pub with_method: KvMethod,
+    /// Value read by `get`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    /// Value replaced by `set` or `delete`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_value: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<KvError>
+}
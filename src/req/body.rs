@@ -0,0 +1,80 @@
+use std::{ops::Deref, str};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use crate::error::{DIDError, DIDErrorKind, ReqField};
+
+/// Body of a request or a response. Bodies are raw bytes on the wire so
+/// storage payloads and encrypted post-`PREFLIGHT` content are carried as-is;
+/// UTF-8 and JSON are only views over those bytes.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DIDBody(Bytes);
+
+impl DIDBody {
+    pub fn new() -> Self {
+        DIDBody(Bytes::new())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Views the body as UTF-8 text.
+    pub fn as_str(&self) -> Result<&str, DIDError> {
+        str::from_utf8(&self.0).map_err(|err| DIDError {
+            kind: DIDErrorKind::MalformedRequest(ReqField::Body),
+            source: "DIDBody::as_str".into(),
+            reason: err.to_string()
+        })
+    }
+
+    /// Deserializes the body as a JSON document.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, DIDError> {
+        serde_json::from_slice(&self.0).map_err(|err| DIDError {
+            kind: DIDErrorKind::MalformedRequest(ReqField::Body),
+            source: "DIDBody::json".into(),
+            reason: err.to_string()
+        })
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for DIDBody {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Bytes> for DIDBody {
+    fn from(bytes: Bytes) -> Self {
+        DIDBody(bytes)
+    }
+}
+
+impl From<Vec<u8>> for DIDBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        DIDBody(Bytes::from(bytes))
+    }
+}
+
+impl From<&'static [u8]> for DIDBody {
+    fn from(bytes: &'static [u8]) -> Self {
+        DIDBody(Bytes::from_static(bytes))
+    }
+}
+
+impl From<String> for DIDBody {
+    fn from(text: String) -> Self {
+        DIDBody(Bytes::from(text))
+    }
+}
+
+impl From<&str> for DIDBody {
+    fn from(text: &str) -> Self {
+        DIDBody(Bytes::copy_from_slice(text.as_bytes()))
+    }
+}
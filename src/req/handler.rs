@@ -0,0 +1,25 @@
+use futures::future::BoxFuture;
+use super::reqres::{DIDRequest, DIDResponse};
+
+/// A route handler. It is implemented for every async function or closure
+/// taking a `DIDRequest` and returning a `DIDResponse`:
+///
+/// ```rust
+/// # use proto_did::req::reqres::{DIDRequest, DIDResponse};
+/// async fn index(req: DIDRequest) -> DIDResponse {
+///     DIDResponse::ok(&req, "hello")
+/// }
+/// ```
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, req: DIDRequest) -> BoxFuture<'static, DIDResponse>;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(DIDRequest) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = DIDResponse> + Send + 'static
+{
+    fn call(&self, req: DIDRequest) -> BoxFuture<'static, DIDResponse> {
+        Box::pin(self(req))
+    }
+}
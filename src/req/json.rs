@@ -0,0 +1,84 @@
+use std::ops::{Deref, DerefMut};
+use serde::{de::DeserializeOwned, Serialize};
+use crate::error::{DIDError, DIDErrorKind};
+use super::{
+    reqres::{DIDRequest, DIDResponse},
+    state::FromRequest};
+
+/// JSON body of a request or a response. As an argument of a `#[did_route]`
+/// function it deserializes the request body, unknown or missing fields
+/// answering `malformed_request:body`; as a return value it answers `OK` with
+/// the serialized value:
+///
+/// ```rust
+/// # use proto_did::{did_route, req::{bodies::{ArEntry, ArSliceRequest}, json::Json}};
+/// #[did_route(#DATA, "/node/neighbors")]
+/// async fn neighbors(query: Json<ArSliceRequest>) -> Json<Vec<ArEntry>> {
+///     Json(Vec::with_capacity(query.get_entries))
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Json<T>(pub T);
+
+impl<T> Json<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(req: &DIDRequest) -> Result<Self, DIDError> {
+        req.body.json::<T>().map(Json)
+    }
+}
+
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Json<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Values a `#[did_route]` function can return, turned into the response to
+/// the request it answers.
+pub trait Responder {
+    fn respond_to(self, req: &DIDRequest) -> DIDResponse;
+}
+
+impl Responder for DIDResponse {
+    fn respond_to(self, _: &DIDRequest) -> DIDResponse {
+        self
+    }
+}
+
+impl<T: Serialize> Responder for Json<T> {
+    fn respond_to(self, req: &DIDRequest) -> DIDResponse {
+        DIDResponse::json(req, &self.0)
+    }
+}
+
+/// Errors are answered with their kind as status, see `DIDResponse::error`.
+impl<R: Responder> Responder for Result<R, DIDError> {
+    fn respond_to(self, req: &DIDRequest) -> DIDResponse {
+        match self {
+            Ok(res) => res.respond_to(req),
+            Err(err) => DIDResponse::error(req, &err)
+        }
+    }
+}
+
+pub(super) fn to_body<T: Serialize>(value: &T) -> Result<Vec<u8>, DIDError> {
+    // The value comes from the server, not from the request.
+    serde_json::to_vec(value).map_err(|err| DIDError {
+        kind: DIDErrorKind::ServerError,
+        source: "Json::respond_to".into(),
+        reason: err.to_string()
+    })
+}
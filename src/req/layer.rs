@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use futures::future::BoxFuture;
+use super::{
+    handler::Handler,
+    reqres::{DIDRequest, DIDResponse}};
+
+/// Middleware run around route dispatch. A layer receives the request and the
+/// rest of the chain: it can inspect or rewrite the request before calling
+/// `next.run(req)`, short-circuit by answering without calling it, or
+/// post-process the response it returns.
+///
+/// It is implemented for every async function or closure taking a
+/// `DIDRequest` and a `Next`:
+///
+/// ```rust
+/// # use proto_did::req::{layer::Next, reqres::{DIDRequest, DIDResponse}};
+/// async fn log(req: DIDRequest, next: Next) -> DIDResponse {
+///     let from = req.did.clone();
+///     let res = next.run(req).await;
+///
+///     println!("{} <- {}", res.status, from);
+///     res
+/// }
+/// ```
+pub trait Layer: Send + Sync + 'static {
+    fn call(&self, req: DIDRequest, next: Next) -> BoxFuture<'static, DIDResponse>;
+}
+
+impl<F, Fut> Layer for F
+where
+    F: Fn(DIDRequest, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = DIDResponse> + Send + 'static
+{
+    fn call(&self, req: DIDRequest, next: Next) -> BoxFuture<'static, DIDResponse> {
+        Box::pin(self(req, next))
+    }
+}
+
+/// The remaining layers of a chain and the handler they wrap.
+pub struct Next {
+    layers: Arc<Vec<Arc<dyn Layer>>>,
+    index: usize,
+    endpoint: Arc<dyn Handler>
+}
+
+impl Next {
+    pub(crate) fn new(
+        layers: Arc<Vec<Arc<dyn Layer>>>,
+        endpoint: Arc<dyn Handler>
+    ) -> Self {
+        Next { layers, endpoint, index: 0 }
+    }
+
+    /// Runs the next layer, or the handler once every layer ran.
+    pub async fn run(mut self, req: DIDRequest) -> DIDResponse {
+        match self.layers.get(self.index).cloned() {
+            Some(layer) => {
+                self.index += 1;
+                layer.call(req, self).await
+            },
+            None => self.endpoint.call(req).await
+        }
+    }
+}
@@ -1,3 +1,12 @@
 pub mod uri;
 pub mod verbs;
+pub mod body;
+pub mod bodies;
+pub mod json;
 pub mod reqres;
+pub mod route;
+pub mod handler;
+pub mod layer;
+mod protocol;
+pub mod router;
+pub mod state;
@@ -0,0 +1,35 @@
+use crate::error::{DIDError, DIDErrorKind};
+use super::reqres::{DIDRequest, DIDResponse};
+
+/// `#DATA` endpoints defined by the README, owned by the crate.
+pub(super) const PROTOCOL_ROUTES: &[&str] = &[
+    "/ar/get",
+    "/ar/score/<did>",
+    "/storage/stats?<provider>",
+    "/storage/occupy",
+    "/storage/create_resource_with_approval_id",
+    "/storage/<did>/<resource_id>",
+    "/storage/<did>/<resource_id>/set",
+    "/storage/<did>/<resource_id>/rm",
+    "/storage/<did>/<resource_id>/kv"
+];
+
+/// First path segments reserved to the protocol endpoints, protocol
+/// extensions can't be registered under them.
+pub(super) const RESERVED_NAMESPACES: &[&str] = &["ar", "storage"];
+
+/// Handler of the protocol endpoints this node does not serve yet, see
+/// `Router::set_protocol_handler`. Answers `server_error`, unlike requests
+/// matching no route.
+pub(super) async fn unsupported(req: DIDRequest) -> DIDResponse {
+    let reason = match &req.url {
+        Some(url) => format!("{} {} is not implemented", req.verb, url.path),
+        None => format!("{} is not implemented", req.verb)
+    };
+
+    DIDResponse::error(&req, &DIDError {
+        kind: DIDErrorKind::ServerError,
+        source: "protocol::unsupported".into(),
+        reason
+    })
+}
@@ -1,104 +1,517 @@
-use std::{fmt::Display, net::Ipv4Addr, str::FromStr};
-use crate::{error::DIDError, identity::DIDIdentity};
-use super::verbs::ReqVerb;
-use url::Url;
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    sync::Arc};
+use bytes::{Bytes, BytesMut};
+use serde::Serialize;
+use tokio_util::codec::Decoder;
+use crate::{
+    error::{DIDError, DIDErrorKind, ReqField},
+    tcp::codec::{DIDFrame, DIDFrameCodec}};
+use super::{
+    body::DIDBody,
+    json,
+    route::RouteParams,
+    state::StateMap,
+    uri::DIDAddress,
+    verbs::ReqVerb};
 
+/// A `did://` request, sent as:
+///
+/// ```text
+/// <VERB>,<URL>,<DID>,<IP>[,id=<ID>],<SIZE>
+///
+/// <BODY>
+/// ```
+///
+/// The optional `id=<ID>` extension tags the request so several requests can
+/// be in flight on the same session: tagged requests are dispatched
+/// concurrently and answered as soon as they complete, with the same id.
+/// Untagged requests are answered in order.
+#[derive(Clone, Debug)]
 pub struct DIDRequest {
-    pub url: Option<Url>,
+    pub url: Option<DIDAddress>,
     pub verb: ReqVerb,
     pub did: String,
     pub req_size: usize,
-    pub ip: Ipv4Addr,
-    pub body: String
+    pub ip: IpAddr,
+    /// Request id, see the `id=<ID>` extension above.
+    pub id: Option<u64>,
+    pub body: DIDBody,
+    /// Parameters extracted by the route template the request matched.
+    pub params: RouteParams,
+    /// Values managed by the server, see `State`.
+    pub state: StateMap
 }
 
-pub struct DIDResponse<'r> {
-    pub from_req: &'r DIDRequest,
-    pub with_identity: &'r DIDIdentity,
-    pub content: String
+/// Response to a `DIDRequest`, sent as:
+///
+/// ```text
+/// <STATUS>,<VERB>,<URL>,<DID>,<IP>[,id=<ID>],<SIZE>
+///
+/// <BODY>
+/// ```
+///
+/// `<VERB>` and `<URL>` are the ones of the answered request, so the response
+/// can be correlated to it, while `<DID>` and `<IP>` are the responder's. The
+/// URL is omitted when answering a request with a reduced header, the id
+/// when answering an untagged request.
+#[derive(Clone, Debug)]
+pub struct DIDResponse {
+    pub status: DIDStatus,
+    pub verb: ReqVerb,
+    pub url: Option<DIDAddress>,
+    pub did: String,
+    pub ip: IpAddr,
+    pub id: Option<u64>,
+    pub body: DIDBody
+}
+
+/// Outcome of a request: `OK`, `NOT_FOUND` when no route matches, or one of
+/// the error kinds of the README. `malformed_request` is followed by the field
+/// at fault on the wire, as in `malformed_request:ip`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DIDStatus {
+    Ok,
+    NotFound,
+    Err(DIDErrorKind)
 }
 
-impl FromStr for DIDRequest {
+/// Header of a frame, `<SIZE>` excluded since it is written and read by
+/// `DIDFrameCodec`. The variant is told apart by the number of fields.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DIDHeader {
+    /// `<VERB>,<URL>,<DID>,<IP>,<SIZE>`, used by every regular request.
+    Full {
+        verb: ReqVerb,
+        url: DIDAddress,
+        did: String,
+        ip: IpAddr
+    },
+    /// `<VERB>,<DID>,<IP>,<SIZE>`, used by the `PREFLIGHT` steps exchanging
+    /// `ECDH_ONLY` session keys, before any URL is involved.
+    Reduced {
+        verb: ReqVerb,
+        did: String,
+        ip: IpAddr
+    }
+}
+
+impl DIDHeader {
+    /// Builds a full header when `url` is set, a reduced one otherwise.
+    pub fn new(
+        verb: ReqVerb, url: Option<DIDAddress>, did: String, ip: IpAddr
+    ) -> Self {
+        match url {
+            Some(url) => Self::Full { verb, url, did, ip },
+            None => Self::Reduced { verb, did, ip }
+        }
+    }
+
+    pub fn into_parts(
+        self
+    ) -> (ReqVerb, Option<DIDAddress>, String, IpAddr) {
+        match self {
+            Self::Full { verb, url, did, ip } => (verb, Some(url), did, ip),
+            Self::Reduced { verb, did, ip } => (verb, None, did, ip)
+        }
+    }
+
+    pub fn verb(&self) -> &ReqVerb {
+        match self {
+            Self::Full { verb, .. } | Self::Reduced { verb, .. } => verb
+        }
+    }
+
+    pub fn did(&self) -> &str {
+        match self {
+            Self::Full { did, .. } | Self::Reduced { did, .. } => did
+        }
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        match self {
+            Self::Full { ip, .. } | Self::Reduced { ip, .. } => *ip
+        }
+    }
+
+    pub fn url(&self) -> Option<&DIDAddress> {
+        match self {
+            Self::Full { url, .. } => Some(url),
+            Self::Reduced { .. } => None
+        }
+    }
+}
+
+impl FromStr for DIDHeader {
     type Err = DIDError;
 
+    /// Parses the header fields of a frame. This never panics: any bad field
+    /// is reported as a `malformed_request` error naming it.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.lines();
-        let mut header = lines.next().get_or_insert("none").split(",");
-
-        let verb = ReqVerb::from_str(header.next().get_or_insert_default())?;
-        let url = Url::from_str(header.next().get_or_insert_default());
-        let mut did = header.next();
-        let ip = Ipv4Addr::from_str(header.next().get_or_insert_default())
-            .unwrap();
-        let size = usize::from_str(header.next().get_or_insert("0")).unwrap();
-
-        header.next();
-
-        let mut body = header.next();
-
-        Ok(DIDRequest {
-            verb, ip,
-            req_size: size,
-            url: if url.is_err() { None } else { Some(url.unwrap()) },
-            did: did.get_or_insert_default().to_string(),
-            body: body.get_or_insert_default().to_string()
-        })
+        let fields = s.split(",").collect::<Vec<&str>>();
+
+        let (verb, url, did, ip) = match fields[..] {
+            [verb, url, did, ip] => (verb, Some(url), did, ip),
+            [verb, did, ip] => (verb, None, did, ip),
+            _ => return Err(malformed(
+                ReqField::Separator,
+                format!("expected 3 or 4 header fields, got {}", fields.len())
+            ))
+        };
+
+        let verb = ReqVerb::from_str(verb)?;
+        let ip = parse_header_ip(ip)?;
+
+        check_did(did)?;
+
+        let did = did.to_string();
+
+        match url {
+            Some(url) => Ok(Self::Full {
+                verb, did, ip,
+                url: DIDAddress::from_str(url)?
+            }),
+            None => Ok(Self::Reduced { verb, did, ip })
+        }
+    }
+}
+
+impl Display for DIDHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full { verb, url, did, ip } => {
+                write!(f, "{},{},{},{}", verb, url, did, HeaderIp(*ip))
+            },
+            Self::Reduced { verb, did, ip } => {
+                write!(f, "{},{},{}", verb, did, HeaderIp(*ip))
+            }
+        }
     }
 }
 
-impl Display for DIDRequest {
+/// Wire encoding of header IPs: IPv4 addresses are written as is and IPv6
+/// addresses between brackets, as in `[::1]`, so they can't be mistaken for
+/// anything else.
+struct HeaderIp(IpAddr);
+
+impl Display for HeaderIp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let url_insert = if self.url.is_some() {
-            format!("{},", self.url.clone().unwrap())
-        } else {
-            String::new()
+        match self.0 {
+            IpAddr::V4(ip) => write!(f, "{}", ip),
+            IpAddr::V6(ip) => write!(f, "[{}]", ip)
+        }
+    }
+}
+
+fn parse_header_ip(ip: &str) -> Result<IpAddr, DIDError> {
+    let parsed = match ip.strip_prefix('[').and_then(|ip| ip.strip_suffix(']')) {
+        Some(ip) => Ipv6Addr::from_str(ip).map(IpAddr::V6),
+        None => Ipv4Addr::from_str(ip).map(IpAddr::V4)
+    };
+
+    parsed.map_err(|err| malformed(ReqField::Ip, format!("{ip:?}: {err}")))
+}
+
+fn check_did(did: &str) -> Result<(), DIDError> {
+    if did.is_empty() || did.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err(malformed(ReqField::Did, format!("invalid did {did:?}")));
+    }
+    Ok(())
+}
+
+/// Splits the optional trailing `id=<ID>` field off the header fields of a
+/// frame.
+fn split_id(header: &str) -> Result<(&str, Option<u64>), DIDError> {
+    let Some((fields, id)) = header.rsplit_once(',') else {
+        return Ok((header, None));
+    };
+    let Some(id) = id.strip_prefix("id=") else {
+        return Ok((header, None));
+    };
+
+    match id.parse::<u64>() {
+        Ok(id) => Ok((fields, Some(id))),
+        Err(err) => Err(malformed(ReqField::Id, format!("{id:?}: {err}")))
+    }
+}
+
+/// Header fields of a frame, with the `id=<ID>` field when `id` is set.
+fn with_id(fields: String, id: Option<u64>) -> String {
+    match id {
+        Some(id) => format!("{fields},id={id}"),
+        None => fields
+    }
+}
+
+fn malformed(field: ReqField, reason: String) -> DIDError {
+    DIDError {
+        kind: DIDErrorKind::MalformedRequest(field),
+        source: "DIDHeader::from_str".into(),
+        reason
+    }
+}
+
+impl TryFrom<DIDFrame> for DIDRequest {
+    type Error = DIDError;
+
+    fn try_from(frame: DIDFrame) -> Result<Self, Self::Error> {
+        let (header, id) = split_id(&frame.header)?;
+        let mut req = DIDRequest::from_header(
+            DIDHeader::from_str(header)?, frame.body.into()
+        );
+
+        req.id = id;
+        Ok(req)
+    }
+}
+
+impl DIDRequest {
+    /// Parses a complete serialized request, header and body included.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DIDError> {
+        let frame = DIDFrameCodec::new()
+            .decode(&mut BytesMut::from(bytes))?
+            .ok_or_else(|| DIDError {
+                kind: DIDErrorKind::MalformedRequest(ReqField::Size),
+                source: "DIDRequest::from_bytes".into(),
+                reason: "incomplete frame".into()
+            })?;
+
+        DIDRequest::try_from(frame)
+    }
+
+    pub fn from_header(header: DIDHeader, body: DIDBody) -> Self {
+        let (verb, url, did, ip) = header.into_parts();
+
+        DIDRequest {
+            verb, url, did, ip,
+            id: None,
+            req_size: body.len(),
+            body,
+            params: RouteParams::default(),
+            state: StateMap::default()
+        }
+    }
+
+    /// `PING` heartbeat sent by `did` from `ip`, see `Heartbeat`.
+    pub fn ping(did: impl Into<String>, ip: IpAddr) -> Self {
+        let header = DIDHeader::Reduced {
+            verb: ReqVerb::Ping,
+            did: did.into(),
+            ip
         };
 
-        let size = self.verb.to_string().len() +
-            self.did.to_string().len() +
-            self.ip.to_string().len() +
-            self.body.len() +
-            url_insert.len() + 5;
+        DIDRequest::from_header(header, DIDBody::new())
+    }
+
+    /// Parses the route parameter `name`, see `RouteParams::get`.
+    pub fn param<T: FromStr>(&self, name: &str) -> Result<T, DIDError> {
+        self.params.get(name)
+    }
 
-        let size = size + size.to_string().len();
+    /// The `T` managed by the server, see `DIDServer::manage`.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get::<T>()
+    }
 
-        write!(f, "{},{}{},{},{}\n\n{}", 
-            self.verb,
-            url_insert,
-            self.did,
-            self.ip,
-            size,
-            self.body
-        ) 
+    /// Raw query string of the request URL.
+    pub fn query(&self) -> Option<&str> {
+        self.url.as_ref()?.query.as_deref()
+    }
+
+    /// Parses the value of `name` in a `key=value&...` query string. A key
+    /// without value is read as an empty string.
+    pub fn query_param<T: FromStr>(&self, name: &str) -> Result<T, DIDError> {
+        let value = self.query()
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+            .find_map(|(key, value)| (key == name).then_some(value));
+
+        let value = value.ok_or_else(|| malformed(
+            ReqField::Url, format!("missing query parameter {name:?}")
+        ))?;
+
+        value.parse::<T>().map_err(|_| malformed(
+            ReqField::Url, format!("invalid query parameter {name:?}: {value:?}")
+        ))
+    }
+
+    /// Requests without an URL use the reduced header.
+    pub fn header(&self) -> DIDHeader {
+        DIDHeader::new(
+            self.verb.clone(), self.url.clone(), self.did.clone(), self.ip
+        )
+    }
+
+    pub fn to_frame(&self) -> DIDFrame {
+        DIDFrame {
+            header: with_id(self.header().to_string(), self.id),
+            body: self.body.clone().into_bytes()
+        }
+    }
+
+    /// Serializes the request exactly as it is sent on the wire.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        self.to_frame().encode_into(&mut buf);
+        buf.freeze()
     }
 }
 
-impl<'r> Display for DIDResponse<'r> {
+impl Display for DIDStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let url_insert = if self.from_req.url.is_some() {
-            format!("{},", self.from_req.url.clone().unwrap())
-        } else {
-            String::new()
-        };
+        match self {
+            Self::Ok => write!(f, "OK"),
+            Self::NotFound => write!(f, "NOT_FOUND"),
+            Self::Err(DIDErrorKind::MalformedRequest(field)) => {
+                write!(f, "malformed_request:{}", field)
+            },
+            Self::Err(kind) => write!(f, "{}", kind)
+        }
+    }
+}
 
-        let size = self.from_req.verb.to_string().len() +
-            self.from_req.did.to_string().len() +
-            self.from_req.ip.to_string().len() +
-            self.content.len() +
-            url_insert.len() + 5;
+impl FromStr for DIDStatus {
+    type Err = DIDError;
+
+    fn from_str(s: &str) -> Result<Self, DIDError> {
+        match s {
+            "OK" => Ok(Self::Ok),
+            "NOT_FOUND" => Ok(Self::NotFound),
+            kind => Ok(Self::Err(DIDErrorKind::from_str(kind)?))
+        }
+    }
+}
 
-        let size = size + size.to_string().len();
+impl DIDResponse {
+    /// Response answering `req`. The responder DID and IP are left empty and
+    /// are filled by the server through `set_responder`, the response can't
+    /// be serialized until then.
+    pub fn new(req: &DIDRequest, status: DIDStatus, body: DIDBody) -> Self {
+        DIDResponse {
+            status, body,
+            verb: req.verb.clone(),
+            url: req.url.clone(),
+            did: String::new(),
+            ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            id: req.id
+        }
+    }
+
+    pub fn ok(req: &DIDRequest, body: impl Into<DIDBody>) -> Self {
+        DIDResponse::new(req, DIDStatus::Ok, body.into())
+    }
 
-        write!(f, "{},{}{},{},{}\n\n{}", 
-            self.from_req.verb,
-            url_insert,
-            self.from_req.did,
-            self.from_req.ip,
-            size,
-            self.content
-        ) 
+    /// `OK` response with `value` serialized as JSON. A value that fails to
+    /// serialize is answered as an error.
+    pub fn json<T: Serialize>(req: &DIDRequest, value: &T) -> Self {
+        match json::to_body(value) {
+            Ok(body) => DIDResponse::ok(req, body),
+            Err(err) => DIDResponse::error(req, &err)
+        }
+    }
+
+    /// Error response carrying `err.reason` as body.
+    pub fn error(req: &DIDRequest, err: &DIDError) -> Self {
+        DIDResponse::new(req, DIDStatus::Err(err.kind), err.reason.clone().into())
+    }
+
+    /// Error response to a frame whose `header` doesn't parse, see `error`.
+    /// The id and the verb of the request are kept when they can be read, so
+    /// the response still reaches the request it answers, while the URL is
+    /// left out. An unknown verb is answered as `DATA`.
+    pub(crate) fn malformed(header: &str, err: &DIDError) -> Self {
+        let (fields, id) = split_id(header).unwrap_or((header, None));
+        let verb = fields.split(',').next()
+            .and_then(|verb| ReqVerb::from_str(verb).ok())
+            .unwrap_or(ReqVerb::Data);
+
+        DIDResponse {
+            verb, id,
+            status: DIDStatus::Err(err.kind),
+            url: None,
+            did: String::new(),
+            ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            body: err.reason.clone().into()
+        }
+    }
+
+    pub fn set_responder(
+        &mut self, did: impl Into<String>, ip: IpAddr
+    ) -> &mut Self {
+        self.did = did.into();
+        self.ip = ip;
+        self
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == DIDStatus::Ok
+    }
+
+    /// Parses a complete serialized response, header and body included.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DIDError> {
+        let frame = DIDFrameCodec::new()
+            .decode(&mut BytesMut::from(bytes))?
+            .ok_or_else(|| DIDError {
+                kind: DIDErrorKind::MalformedRequest(ReqField::Size),
+                source: "DIDResponse::from_bytes".into(),
+                reason: "incomplete frame".into()
+            })?;
+
+        DIDResponse::try_from(frame)
+    }
+
+    pub fn header(&self) -> DIDHeader {
+        DIDHeader::new(
+            self.verb.clone(), self.url.clone(), self.did.clone(), self.ip
+        )
+    }
+
+    /// Fails with `server_error` when the responder DID is missing or
+    /// invalid, see `set_responder`: the frame couldn't be parsed back.
+    pub fn to_frame(&self) -> Result<DIDFrame, DIDError> {
+        check_did(&self.did).map_err(|err| DIDError {
+            kind: DIDErrorKind::ServerError,
+            source: "DIDResponse::to_frame".into(),
+            reason: format!("responder not set: {}", err.reason)
+        })?;
+
+        Ok(DIDFrame {
+            header: with_id(
+                format!("{},{}", self.status, self.header()), self.id
+            ),
+            body: self.body.clone().into_bytes()
+        })
+    }
+
+    /// Serializes the response exactly as it is sent on the wire, see
+    /// `to_frame`.
+    pub fn to_bytes(&self) -> Result<Bytes, DIDError> {
+        let mut buf = BytesMut::new();
+
+        self.to_frame()?.encode_into(&mut buf);
+        Ok(buf.freeze())
     }
 }
 
-// TODO: Implement macros to generate request handler configs with request
-// handlers from basic functions, like rocket.rs does.
+impl TryFrom<DIDFrame> for DIDResponse {
+    type Error = DIDError;
+
+    fn try_from(frame: DIDFrame) -> Result<Self, Self::Error> {
+        let Some((status, header)) = frame.header.split_once(",") else {
+            return Err(malformed(ReqField::Separator, "missing status".into()));
+        };
+
+        let status = DIDStatus::from_str(status)?;
+        let (header, id) = split_id(header)?;
+        let (verb, url, did, ip) = DIDHeader::from_str(header)?.into_parts();
+
+        Ok(DIDResponse {
+            status, verb, url, did, ip, id,
+            body: frame.body.into()
+        })
+    }
+}
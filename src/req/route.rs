@@ -0,0 +1,222 @@
+use std::{collections::HashMap, fmt::Display, pin::Pin, str::FromStr};
+use crate::error::{DIDError, DIDErrorKind, ReqField};
+use super::{
+    reqres::{DIDRequest, DIDResponse},
+    uri::DIDAddress,
+    verbs::ReqVerb};
+
+/// A route declared with `#[did_route]`, collected by `routes![...]` and
+/// registered with `DIDServer::add_routes`.
+pub struct DIDRoute {
+    pub verb: ReqVerb,
+    pub path: &'static str,
+    pub handler: fn(
+        DIDRequest
+    ) -> Pin<Box<dyn Future<Output = DIDResponse> + Send>>
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Segment {
+    Static(String),
+    /// `<name>`, a single path segment.
+    Param(String),
+    /// `<name..>`, every remaining segment, possibly none.
+    Tail(String)
+}
+
+/// Path template of a route, with named segments and a wildcard tail:
+/// `/ar/score/<did>`, `/storage/<did>/<resource_id>/set`, `/files/<path..>`.
+///
+/// A template may end with `?<name>` to bind the raw query string of the
+/// request to `name`, as in `/storage/stats?<provider>`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RouteTemplate {
+    segments: Vec<Segment>,
+    query: Option<String>
+}
+
+impl RouteTemplate {
+    /// Matches `address` against the template, returning the extracted
+    /// parameters.
+    pub fn matches(&self, address: &DIDAddress) -> Option<RouteParams> {
+        let mut params = RouteParams::default();
+        let mut path = address.path.split('/').filter(|s| !s.is_empty());
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Static(expected) => {
+                    if path.next()? != expected {
+                        return None;
+                    }
+                },
+                Segment::Param(name) => {
+                    params.insert(name, path.next()?);
+                },
+                Segment::Tail(name) => {
+                    let tail = path.by_ref().collect::<Vec<&str>>();
+
+                    params.insert(name, &tail.join("/"));
+                }
+            }
+        }
+
+        if path.next().is_some() {
+            return None;
+        }
+
+        match (&self.query, &address.query) {
+            (Some(name), Some(query)) => params.insert(name, query),
+            (Some(_), None) => return None,
+            (None, _) => {}
+        }
+
+        Some(params)
+    }
+
+    /// First segment of the template when it is static, `ar` for
+    /// `/ar/score/<did>`.
+    pub fn namespace(&self) -> Option<&str> {
+        match self.segments.first()? {
+            Segment::Static(name) => Some(name),
+            _ => None
+        }
+    }
+
+    /// Whether the first segment is a parameter or a tail, matching paths
+    /// under any namespace.
+    pub(super) fn any_namespace(&self) -> bool {
+        matches!(
+            self.segments.first(),
+            Some(Segment::Param(_) | Segment::Tail(_))
+        )
+    }
+
+    /// Ordering key used when several templates match the same request:
+    /// static segments win over parameters, which win over tails.
+    pub fn rank(&self) -> (usize, usize, bool) {
+        let statics = self.segments.iter()
+            .filter(|s| matches!(s, Segment::Static(_)))
+            .count();
+        let params = self.segments.iter()
+            .filter(|s| matches!(s, Segment::Param(_)))
+            .count();
+
+        (statics, params, self.query.is_some())
+    }
+
+    /// Whether some path matches both templates.
+    pub fn overlaps(&self, other: &RouteTemplate) -> bool {
+        let tail = |template: &RouteTemplate| {
+            matches!(template.segments.last(), Some(Segment::Tail(_)))
+        };
+        let fixed = |template: &RouteTemplate| {
+            template.segments.len() - tail(template) as usize
+        };
+        let lengths = match (tail(self), tail(other)) {
+            (false, false) => fixed(self) == fixed(other),
+            (true, false) => fixed(other) >= fixed(self),
+            (false, true) => fixed(self) >= fixed(other),
+            (true, true) => true
+        };
+
+        lengths && self.segments.iter().zip(&other.segments).all(|pair| {
+            match pair {
+                (Segment::Static(a), Segment::Static(b)) => a == b,
+                _ => true
+            }
+        })
+    }
+}
+
+impl FromStr for RouteTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, query) = match s.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (s, None)
+        };
+
+        if !path.starts_with('/') {
+            return Err(format!("route {s:?} must start with /"));
+        }
+
+        let mut segments = vec![];
+
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            if matches!(segments.last(), Some(Segment::Tail(_))) {
+                return Err(format!("route {s:?}: <name..> must be last"));
+            }
+
+            segments.push(match param_name(segment) {
+                Some(name) => match name.strip_suffix("..") {
+                    Some(name) => Segment::Tail(name.to_string()),
+                    None => Segment::Param(name.to_string())
+                },
+                None => Segment::Static(segment.to_string())
+            });
+        }
+
+        let query = match query {
+            Some(query) => Some(param_name(query)
+                .ok_or_else(|| format!("route {s:?}: query must be ?<name>"))?
+                .to_string()),
+            None => None
+        };
+
+        Ok(RouteTemplate { segments, query })
+    }
+}
+
+fn param_name(segment: &str) -> Option<&str> {
+    segment.strip_prefix('<')?.strip_suffix('>')
+}
+
+impl Display for RouteTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.segments.is_empty() {
+            write!(f, "/")?;
+        }
+        for segment in &self.segments {
+            match segment {
+                Segment::Static(name) => write!(f, "/{}", name)?,
+                Segment::Param(name) => write!(f, "/<{}>", name)?,
+                Segment::Tail(name) => write!(f, "/<{}..>", name)?
+            }
+        }
+        if let Some(query) = &self.query {
+            write!(f, "?<{}>", query)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parameters extracted from the URL of a request by its route template.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RouteParams(HashMap<String, String>);
+
+impl RouteParams {
+    fn insert(&mut self, name: &str, value: &str) {
+        self.0.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn get_raw(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|value| value.as_str())
+    }
+
+    /// Parses the parameter `name` as `T`. A missing or invalid parameter is
+    /// a `malformed_request` on the URL.
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<T, DIDError> {
+        let value = self.get_raw(name).ok_or_else(|| DIDError {
+            kind: DIDErrorKind::MalformedRequest(ReqField::Url),
+            source: "RouteParams::get".into(),
+            reason: format!("missing parameter {name:?}")
+        })?;
+
+        value.parse::<T>().map_err(|_| DIDError {
+            kind: DIDErrorKind::MalformedRequest(ReqField::Url),
+            source: "RouteParams::get".into(),
+            reason: format!("invalid parameter {name:?}: {value:?}")
+        })
+    }
+}
@@ -0,0 +1,281 @@
+use std::sync::Arc;
+use futures::future::BoxFuture;
+use super::{
+    handler::Handler,
+    layer::{Layer, Next},
+    protocol::{self, PROTOCOL_ROUTES, RESERVED_NAMESPACES},
+    reqres::{DIDRequest, DIDResponse, DIDStatus},
+    route::{DIDRoute, RouteTemplate},
+    state::StateMap,
+    verbs::ReqVerb};
+
+#[derive(Clone)]
+struct Route {
+    verb: ReqVerb,
+    template: RouteTemplate,
+    layers: Arc<Vec<Arc<dyn Layer>>>,
+    handler: Arc<dyn Handler>
+}
+
+/// Route table of a `DIDServer`, matching requests on their verb and the path
+/// of their URL against route templates (see `RouteTemplate`).
+///
+/// `#DATA` is reserved to the protocol endpoints defined by the README, which
+/// every router registers and answers as `server_error` until they are
+/// backed with `set_protocol_handler`. `add_route` refuses `#DATA` routes,
+/// extensions to the protocol are registered with `extend_protocol` instead.
+///
+/// Requests go through the global layers first, in the order they were
+/// added, are then routed, and go through the layers of their route before
+/// reaching its handler. Global layers therefore also see requests that match
+/// no route, and may rewrite their URL.
+#[derive(Clone)]
+pub struct Router {
+    routes: Arc<Vec<Route>>,
+    layers: Arc<Vec<Arc<dyn Layer>>>,
+    state: StateMap
+}
+
+/// Final step of the global layers chain: finds the route and runs its own
+/// chain.
+struct RouteEndpoint {
+    routes: Arc<Vec<Route>>
+}
+
+impl Router {
+    pub fn new() -> Self {
+        let mut router = Router {
+            routes: Arc::new(vec![]),
+            layers: Arc::new(vec![]),
+            state: StateMap::default()
+        };
+
+        for path in PROTOCOL_ROUTES {
+            router.insert_route(ReqVerb::HashData, path, protocol::unsupported);
+        }
+        router
+    }
+
+    fn template(path: &str) -> RouteTemplate {
+        path.parse::<RouteTemplate>()
+            .unwrap_or_else(|err| panic!("invalid route: {err}"))
+    }
+
+    /// Registers `handler` for `verb` and the template `path`. Registering
+    /// the same verb and path twice replaces the previous handler and its
+    /// layers.
+    ///
+    /// Panics if `path` is not a valid route template, if `verb` is `#DATA`
+    /// (see `extend_protocol`) or `PING`, answered by the server itself, or
+    /// if `path` is ambiguous: as specific as another template of `verb`
+    /// (see `RouteTemplate::rank`) matching some of the same paths, such as
+    /// `/a/<id>` and `/<name>/b`.
+    pub fn add_route(
+        &mut self,
+        verb: ReqVerb,
+        path: &str,
+        handler: impl Handler
+    ) -> &mut Self {
+        if verb == ReqVerb::HashData {
+            panic!(
+                "#DATA {path}: #DATA is reserved to the protocol, use \
+                    Router::extend_protocol"
+            );
+        }
+        if verb == ReqVerb::Ping {
+            panic!("PING {path}: heartbeats are answered by the server");
+        }
+        self.insert_route(verb, path, handler)
+    }
+
+    /// Registers `handler` as a `#DATA` protocol extension on `path`.
+    ///
+    /// Panics if `path` is not a valid route template, if it falls under a
+    /// namespace of the protocol endpoints (`/ar`, `/storage`) or if its
+    /// first segment is a parameter, which could match them.
+    pub fn extend_protocol(
+        &mut self,
+        path: &str,
+        handler: impl Handler
+    ) -> &mut Self {
+        let template = Router::template(path);
+
+        let reserved = template.namespace()
+            .is_some_and(|namespace| RESERVED_NAMESPACES.contains(&namespace));
+
+        if reserved || template.any_namespace() {
+            panic!("#DATA {path} clashes with the protocol endpoints");
+        }
+        self.insert_route(ReqVerb::HashData, path, handler)
+    }
+
+    /// Serves the protocol endpoint `path` with `handler`, such as `/ar/get`
+    /// answered from an AR table kept by the application.
+    ///
+    /// Panics if `path` is not one of the `#DATA` endpoints of the README,
+    /// written as in `/ar/score/<did>` (see `extend_protocol`).
+    pub fn set_protocol_handler(
+        &mut self,
+        path: &str,
+        handler: impl Handler
+    ) -> &mut Self {
+        if !Router::is_protocol_route(&Router::template(path)) {
+            panic!("#DATA {path} is not a protocol endpoint");
+        }
+        self.insert_route(ReqVerb::HashData, path, handler)
+    }
+
+    fn is_protocol_route(template: &RouteTemplate) -> bool {
+        PROTOCOL_ROUTES.iter().any(|path| Router::template(path) == *template)
+    }
+
+    fn insert_route(
+        &mut self,
+        verb: ReqVerb,
+        path: &str,
+        handler: impl Handler
+    ) -> &mut Self {
+        let template = Router::template(path);
+        let routes = Arc::make_mut(&mut self.routes);
+
+        routes.retain(|route| route.verb != verb || route.template != template);
+
+        let ambiguous = routes.iter().find(|route| {
+            route.verb == verb
+                && route.template.rank() == template.rank()
+                && route.template.overlaps(&template)
+        });
+
+        if let Some(route) = ambiguous {
+            panic!("{verb} {template} is ambiguous with {}", route.template);
+        }
+        routes.push(Route {
+            verb, template,
+            layers: Arc::new(vec![]),
+            handler: Arc::new(handler)
+        });
+        self
+    }
+
+    /// Registers routes declared with `#[did_route]`. `#DATA` routes back the
+    /// protocol endpoint they name, or are registered as protocol extensions.
+    pub fn add_routes(&mut self, routes: Vec<DIDRoute>) -> &mut Self {
+        for route in routes {
+            let template = Router::template(route.path);
+
+            match route.verb {
+                ReqVerb::HashData if Router::is_protocol_route(&template) => {
+                    self.set_protocol_handler(route.path, route.handler)
+                },
+                ReqVerb::HashData => {
+                    self.extend_protocol(route.path, route.handler)
+                },
+                verb => self.add_route(verb, route.path, route.handler)
+            };
+        }
+        self
+    }
+
+    /// Adds a layer run around every request.
+    pub fn add_layer(&mut self, layer: impl Layer) -> &mut Self {
+        Arc::make_mut(&mut self.layers).push(Arc::new(layer));
+        self
+    }
+
+    /// Adds a layer run around the handler of the route registered for `verb`
+    /// and `path`, after the global layers.
+    ///
+    /// Panics if no such route is registered.
+    pub fn add_route_layer(
+        &mut self,
+        verb: ReqVerb,
+        path: &str,
+        layer: impl Layer
+    ) -> &mut Self {
+        let template = Router::template(path);
+        let route = Arc::make_mut(&mut self.routes)
+            .iter_mut()
+            .find(|route| route.verb == verb && route.template == template)
+            .unwrap_or_else(|| panic!("no route for {verb} {template}"));
+
+        Arc::make_mut(&mut route.layers).push(Arc::new(layer));
+        self
+    }
+
+    /// Shares `value` with every handler and layer, see `State`.
+    pub fn manage<T: Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
+        self.state.insert(value);
+        self
+    }
+
+    /// Runs `req` through the layers and the handler matching it, or answers
+    /// `NOT_FOUND`. Requests without URL (reduced header) never match a
+    /// route.
+    pub async fn dispatch(&self, mut req: DIDRequest) -> DIDResponse {
+        req.state = self.state.clone();
+
+        let endpoint = Arc::new(RouteEndpoint { routes: self.routes.clone() });
+
+        Next::new(self.layers.clone(), endpoint).run(req).await
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+impl RouteEndpoint {
+    /// Returns the most specific route matching `req` and fills the request
+    /// parameters from it. Ambiguous routes being refused, it is unique.
+    fn find(&self, req: &mut DIDRequest) -> Option<&Route> {
+        let url = req.url.as_ref()?;
+        let (route, params) = self.routes.iter()
+            .filter(|route| route.verb == req.verb)
+            .filter_map(|route| Some((route, route.template.matches(url)?)))
+            .max_by_key(|(route, _)| route.template.rank())?;
+
+        req.params = params;
+        Some(route)
+    }
+}
+
+impl Handler for RouteEndpoint {
+    fn call(&self, mut req: DIDRequest) -> BoxFuture<'static, DIDResponse> {
+        let Some(route) = self.find(&mut req) else {
+            let reason = match &req.url {
+                Some(url) => format!("no route for {} {}", req.verb, url.path),
+                None => format!("no route for {}", req.verb)
+            };
+            let res = DIDResponse::new(&req, DIDStatus::NotFound, reason.into());
+
+            return Box::pin(async move { res });
+        };
+
+        Box::pin(Next::new(route.layers.clone(), route.handler.clone()).run(req))
+    }
+}
+
+/// Exposes the dispatcher to tower middlewares. Routing never fails, errors
+/// are answered as responses. Their responder is left to the caller, see
+/// `DIDResponse::set_responder`.
+#[cfg(feature = "tower")]
+impl tower_service::Service<DIDRequest> for Router {
+    type Response = DIDResponse;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<'static, Result<DIDResponse, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        _: &mut std::task::Context<'_>
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: DIDRequest) -> Self::Future {
+        let router = self.clone();
+
+        Box::pin(async move { Ok(router.dispatch(req).await) })
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+    ops::Deref,
+    sync::Arc};
+use crate::error::{DIDError, DIDErrorKind};
+use super::reqres::DIDRequest;
+
+/// Values registered with `DIDServer::manage`, one per type. The map is
+/// shared by every connection task and cloning it only clones a pointer.
+#[derive(Clone, Default)]
+pub struct StateMap(Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>);
+
+impl StateMap {
+    /// Registers `value`, replacing a previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        Arc::make_mut(&mut self.0).insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.0.get(&TypeId::of::<T>())?.clone().downcast::<T>().ok()
+    }
+}
+
+impl Debug for StateMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StateMap({} values)", self.0.len())
+    }
+}
+
+/// Values a handler can take from the request it answers. Used by
+/// `#[did_route]` for arguments that are neither route parameters, `#[body]`
+/// nor `#[sender]`.
+pub trait FromRequest: Sized {
+    fn from_request(req: &DIDRequest) -> Result<Self, DIDError>;
+}
+
+impl FromRequest for DIDRequest {
+    fn from_request(req: &DIDRequest) -> Result<Self, DIDError> {
+        Ok(req.clone())
+    }
+}
+
+/// Shared value of type `T` registered with `DIDServer::manage`:
+///
+/// ```rust
+/// # use proto_did::req::{reqres::{DIDRequest, DIDResponse}, state::State};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// struct Counter {
+///     hits: AtomicUsize
+/// }
+///
+/// async fn count(req: DIDRequest) -> DIDResponse {
+///     let Some(counter) = State::<Counter>::from_req(&req) else {
+///         return DIDResponse::ok(&req, "counting is disabled");
+///     };
+///     let n = counter.hits.fetch_add(1, Ordering::Relaxed);
+///
+///     DIDResponse::ok(&req, n.to_string())
+/// }
+/// ```
+pub struct State<T>(pub Arc<T>);
+
+impl<T: Send + Sync + 'static> State<T> {
+    /// Takes the `T` managed by the server of `req`, if any.
+    pub fn from_req(req: &DIDRequest) -> Option<Self> {
+        req.state.get::<T>().map(State)
+    }
+}
+
+impl<T: Send + Sync + 'static> FromRequest for State<T> {
+    /// A `T` that isn't managed is a setup error rather than a faulty
+    /// request, answered as `server_error`.
+    fn from_request(req: &DIDRequest) -> Result<Self, DIDError> {
+        State::from_req(req).ok_or_else(|| DIDError {
+            kind: DIDErrorKind::ServerError,
+            source: "State::from_request".into(),
+            reason: format!(
+                "no {} is managed, register it with DIDServer::manage",
+                type_name::<T>()
+            )
+        })
+    }
+}
+
+impl<T> Clone for State<T> {
+    fn clone(&self) -> Self {
+        State(self.0.clone())
+    }
+}
+
+impl<T> Deref for State<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
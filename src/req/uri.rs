@@ -1,19 +1,240 @@
-use std::fmt::Display;
-use url::Url;
-use super::verbs::ReqVerb;
+use std::{fmt::Display, net::Ipv6Addr, str::FromStr};
+use crate::error::{DIDError, DIDErrorKind, ReqField};
 
-/// Describes a DID URI and a verb, it can be used with an absolute URI (with
-/// protocol) therefore Some(url), or a relative one (absolute path) with
-/// Some(path)
-#[derive(Eq, Hash, PartialEq)]
-pub struct DIDUri {
-    pub url: Option<Url>,
-    pub path: Option<String>,
-    pub verb: ReqVerb
+/// Host part of a `did://` address.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum DIDAuthority {
+    /// `did://<address>`, the public key of the target, or its IP address,
+    /// IPv6 addresses being written between brackets as in `did://[::1]`.
+    Raw(String),
+    /// `did://<dns>:<common_name>` or `did://<dns>:<tld>:<common_name>`, a
+    /// common name resolved through a DNS DID. Without a TLD, the DNS is
+    /// looked for under `.com`.
+    Dns {
+        dns: String,
+        tld: Option<String>,
+        common_name: String
+    }
+}
+
+/// Kind of a `did://` address, see `DIDAuthority`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AddressKind {
+    Raw,
+    Dns
+}
+
+/// Lookup suffix of a `did://` address.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LookupMode {
+    /// `did://<address>?`, target lookup with `WHERE?`.
+    Target,
+    /// `did://<address>!`, target with storage lookup with `WHERE!`.
+    Storage
+}
+
+/// A parsed `did://` address. The forms defined by the README are:
+/// - `did://<address>/<path>?<query>`
+/// - `did://<dns>:<common_name>/<path>?<query>`
+/// - `did://<dns>:<tld>:<common_name>/<path>?<query>`
+/// - any of the above without path, followed by a `?` or `!` lookup suffix.
+///
+/// Commas and whitespaces are refused anywhere since the address is carried
+/// in comma separated headers. Paths are made of non-empty segments, other
+/// than `.` and `..`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DIDAddress {
+    pub authority: DIDAuthority,
+    /// Absolute path, `/` when the address has none.
+    pub path: String,
+    pub query: Option<String>,
+    pub lookup: Option<LookupMode>
+}
+
+impl DIDAddress {
+    pub fn kind(&self) -> AddressKind {
+        match self.authority {
+            DIDAuthority::Raw(_) => AddressKind::Raw,
+            DIDAuthority::Dns { .. } => AddressKind::Dns
+        }
+    }
+
+    /// The target public key, for raw addresses.
+    pub fn address(&self) -> Option<&str> {
+        match &self.authority {
+            DIDAuthority::Raw(address) => Some(address),
+            DIDAuthority::Dns { .. } => None
+        }
+    }
+
+    pub fn dns(&self) -> Option<&str> {
+        match &self.authority {
+            DIDAuthority::Dns { dns, .. } => Some(dns),
+            DIDAuthority::Raw(_) => None
+        }
+    }
+
+    pub fn tld(&self) -> Option<&str> {
+        match &self.authority {
+            DIDAuthority::Dns { tld, .. } => tld.as_deref(),
+            DIDAuthority::Raw(_) => None
+        }
+    }
+
+    pub fn common_name(&self) -> Option<&str> {
+        match &self.authority {
+            DIDAuthority::Dns { common_name, .. } => Some(common_name),
+            DIDAuthority::Raw(_) => None
+        }
+    }
+
+    /// HTTP host of the DNS DID, `<dns>.<tld>` or `<dns>.com`.
+    pub fn dns_host(&self) -> Option<String> {
+        match &self.authority {
+            DIDAuthority::Dns { dns, tld, .. } => Some(format!(
+                "{}.{}", dns, tld.as_deref().unwrap_or("com")
+            )),
+            DIDAuthority::Raw(_) => None
+        }
+    }
+
+    fn error(reason: String) -> DIDError {
+        DIDError {
+            kind: DIDErrorKind::MalformedRequest(ReqField::Url),
+            source: "DIDAddress::from_str".into(),
+            reason
+        }
+    }
+
+    fn check_segment(segment: &str, allowed: &[char]) -> Result<(), DIDError> {
+        let valid = !segment.is_empty() && segment.chars().all(|c| {
+            c.is_ascii_alphanumeric() || allowed.contains(&c)
+        });
+
+        if !valid {
+            return Err(DIDAddress::error(format!("invalid segment {segment:?}")));
+        }
+        Ok(())
+    }
+
+    fn check_path(path: &str) -> Result<(), DIDError> {
+        if path == "/" {
+            return Ok(());
+        }
+
+        let valid = path.strip_prefix('/').is_some_and(|path| {
+            path.split('/').all(|segment| {
+                !matches!(segment, "" | "." | "..")
+            })
+        });
+
+        if !valid {
+            return Err(DIDAddress::error(format!("invalid path {path:?}")));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DIDAddress {
+    type Err = DIDError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(rest) = s.strip_prefix("did://") else {
+            return Err(DIDAddress::error(format!("{s:?} is not a did:// URL")));
+        };
+
+        if rest.contains(|c: char| c == ',' || c.is_whitespace()) {
+            return Err(DIDAddress::error(format!("{s:?} contains separators")));
+        }
+
+        let authority_end = rest.find(['/', '?', '!']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(authority_end);
+        let authority = match authority.split(":").collect::<Vec<&str>>()[..] {
+            _ if authority.starts_with('[') => {
+                let ip = authority.strip_prefix('[')
+                    .and_then(|ip| ip.strip_suffix(']'))
+                    .and_then(|ip| Ipv6Addr::from_str(ip).ok());
+
+                if ip.is_none() {
+                    return Err(DIDAddress::error(
+                        format!("invalid authority {authority:?}")
+                    ));
+                }
+                DIDAuthority::Raw(authority.to_string())
+            },
+            [address] => {
+                DIDAddress::check_segment(address, &['.', '-', '_'])?;
+                DIDAuthority::Raw(address.to_string())
+            },
+            [dns, common_name] => {
+                DIDAddress::check_segment(dns, &['-'])?;
+                DIDAddress::check_segment(common_name, &['-', '_'])?;
+                DIDAuthority::Dns {
+                    dns: dns.to_string(),
+                    tld: None,
+                    common_name: common_name.to_string()
+                }
+            },
+            [dns, tld, common_name] => {
+                DIDAddress::check_segment(dns, &['-'])?;
+                DIDAddress::check_segment(tld, &[])?;
+                DIDAddress::check_segment(common_name, &['-', '_'])?;
+                DIDAuthority::Dns {
+                    dns: dns.to_string(),
+                    tld: Some(tld.to_string()),
+                    common_name: common_name.to_string()
+                }
+            },
+            _ => return Err(DIDAddress::error(
+                format!("invalid authority {authority:?}")
+            ))
+        };
+
+        let (path, query, lookup) = match rest {
+            "" => ("/", None, None),
+            "?" => ("/", None, Some(LookupMode::Target)),
+            "!" => ("/", None, Some(LookupMode::Storage)),
+            rest if rest.starts_with('/') => match rest.split_once('?') {
+                Some((path, query)) => (path, Some(query.to_string()), None),
+                None => (rest, None, None)
+            },
+            rest => return Err(DIDAddress::error(
+                format!("unexpected {rest:?} after the authority")
+            ))
+        };
+
+        DIDAddress::check_path(path)?;
+
+        Ok(DIDAddress {
+            authority, query, lookup,
+            path: path.to_string()
+        })
+    }
 }
 
-impl Display for DIDUri {
+impl Display for DIDAuthority {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.verb, self.verb)
+        match self {
+            Self::Raw(address) => write!(f, "{}", address),
+            Self::Dns { dns, tld: Some(tld), common_name } => {
+                write!(f, "{}:{}:{}", dns, tld, common_name)
+            },
+            Self::Dns { dns, tld: None, common_name } => {
+                write!(f, "{}:{}", dns, common_name)
+            }
+        }
+    }
+}
+
+impl Display for DIDAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "did://{}", self.authority)?;
+
+        match (self.lookup, &self.query) {
+            (Some(LookupMode::Target), _) => write!(f, "?"),
+            (Some(LookupMode::Storage), _) => write!(f, "!"),
+            (None, Some(query)) => write!(f, "{}?{}", self.path, query),
+            (None, None) => write!(f, "{}", self.path)
+        }
     }
 }
@@ -1,8 +1,8 @@
 use std::{fmt::Display, str::FromStr};
-use crate::error::DIDError;
+use crate::error::{DIDError, DIDErrorKind, ReqField};
 
 /// Implementation of request verbs
-#[derive(Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum ReqVerb {
     /// PREFLIGHT
     Preflight,
@@ -13,7 +13,9 @@ pub enum ReqVerb {
     /// #DATA
     HashData,
     /// DATA
-    Data
+    Data,
+    /// PING, heartbeat answered by the server itself, see `Heartbeat`
+    Ping
 }
 
 impl Display for ReqVerb {
@@ -23,7 +25,8 @@ impl Display for ReqVerb {
             Self::Where => "WHERE?",
             Self::WhereStorage => "WHERE!",
             Self::HashData => "#DATA",
-            Self::Data => "DATA"
+            Self::Data => "DATA",
+            Self::Ping => "PING"
         };
 
         write!(f, "{}", mode)
@@ -40,7 +43,9 @@ impl FromStr for ReqVerb {
             "WHERE!" => Ok(Self::WhereStorage),
             "#DATA" => Ok(Self::HashData),
             "DATA" => Ok(Self::Data),
+            "PING" => Ok(Self::Ping),
             _ => Err(DIDError {
+                kind: DIDErrorKind::MalformedRequest(ReqField::Verb),
                 source: "ReqVerbs::from_str".to_string(),
                 reason: "unknown verb".to_string()
             })
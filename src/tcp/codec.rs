@@ -0,0 +1,134 @@
+use std::str;
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use crate::error::{DIDError, DIDErrorKind, ReqField};
+
+/// Default upper bound for a header line, separator excluded.
+const MAX_HEADER_LEN: usize = 8 * 1024;
+/// Default upper bound for a frame body.
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+/// A single `did://` frame as it travels on the wire:
+///
+/// ```text
+/// <HEADER>,<SIZE>
+///
+/// <BODY>
+/// ```
+///
+/// `header` holds every header field except `<SIZE>`, which is a framing
+/// concern only: it is always the last field and the number of bytes of
+/// `<BODY>`, and is written and read by `DIDFrameCodec`. Both the full and the
+/// reduced headers (see `DIDHeader`) are framed the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DIDFrame {
+    pub header: String,
+    pub body: Bytes
+}
+
+impl DIDFrame {
+    /// Appends the wire representation of the frame to `dst`.
+    pub fn encode_into(&self, dst: &mut BytesMut) {
+        let size = self.body.len().to_string();
+
+        dst.reserve(self.header.len() + size.len() + self.body.len() + 3);
+        dst.put_slice(self.header.as_bytes());
+        dst.put_u8(b',');
+        dst.put_slice(size.as_bytes());
+        dst.put_slice(b"\n\n");
+        dst.put_slice(&self.body);
+    }
+}
+
+/// Length-delimited codec for `did://` frames. It tolerates partial reads
+/// (the frame is only yielded once the whole body is buffered) and several
+/// frames written back to back in the same buffer.
+#[derive(Clone, Debug)]
+pub struct DIDFrameCodec {
+    max_header_len: usize,
+    max_body_len: usize
+}
+
+impl DIDFrameCodec {
+    pub fn new() -> Self {
+        DIDFrameCodec {
+            max_header_len: MAX_HEADER_LEN,
+            max_body_len: MAX_BODY_LEN
+        }
+    }
+
+    pub fn set_max_body_len(&mut self, max_body_len: usize) -> &mut Self {
+        self.max_body_len = max_body_len;
+        self
+    }
+
+    fn error(field: ReqField, reason: &str) -> DIDError {
+        DIDError {
+            kind: DIDErrorKind::MalformedRequest(field),
+            source: "DIDFrameCodec::decode".into(),
+            reason: reason.into()
+        }
+    }
+}
+
+impl Default for DIDFrameCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for DIDFrameCodec {
+    type Item = DIDFrame;
+    type Error = DIDError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<DIDFrame>, DIDError> {
+        let Some(header_len) = src.iter().position(|b| *b == b'\n') else {
+            if src.len() > self.max_header_len {
+                return Err(Self::error(ReqField::Header, "header too long"));
+            }
+            return Ok(None);
+        };
+
+        if header_len > self.max_header_len {
+            return Err(Self::error(ReqField::Header, "header too long"));
+        }
+        if src.len() < header_len + 2 {
+            return Ok(None);
+        }
+        if src[header_len + 1] != b'\n' {
+            return Err(Self::error(ReqField::Separator, "missing blank line after header"));
+        }
+
+        let header = str::from_utf8(&src[..header_len])
+            .map_err(|_| Self::error(ReqField::Header, "header is not valid UTF-8"))?;
+        let (fields, size) = header.rsplit_once(',')
+            .ok_or_else(|| Self::error(ReqField::Size, "missing size field"))?;
+        let size = size.parse::<usize>()
+            .map_err(|_| Self::error(ReqField::Size, "invalid size field"))?;
+
+        if size > self.max_body_len {
+            return Err(Self::error(ReqField::Size, "body too long"));
+        }
+
+        let frame_len = header_len + 2 + size;
+
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        let header = fields.to_string();
+        let body = src.split_to(frame_len).freeze().slice(header_len + 2..);
+
+        Ok(Some(DIDFrame { header, body }))
+    }
+}
+
+impl Encoder<DIDFrame> for DIDFrameCodec {
+    type Error = DIDError;
+
+    fn encode(&mut self, item: DIDFrame, dst: &mut BytesMut) -> Result<(), DIDError> {
+        item.encode_into(dst);
+        Ok(())
+    }
+}
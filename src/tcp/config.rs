@@ -0,0 +1,176 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration};
+use super::listener::BindAddr;
+
+/// Listener and connection options of a `DIDServer`:
+///
+/// ```rust
+/// # use proto_did::{BindAddr, DIDServerConfig};
+/// # use std::time::Duration;
+/// let mut config = DIDServerConfig::new();
+///
+/// config
+///     .set_bind_addrs(&[BindAddr::DualStack])
+///     .set_port(5173)
+///     .set_reuse_addr(true)
+///     .set_keepalive(Some(Duration::from_secs(60)));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct DIDServerConfig {
+    /// Addresses the server listens on, `127.0.0.1` by default.
+    pub bind_addrs: Vec<BindAddr>,
+    /// Port of every bind address, `0` picks an ephemeral port.
+    pub port: u16,
+    /// Length of the pending connections queue of each listener.
+    pub backlog: i32,
+    /// `SO_REUSEADDR` on the listeners.
+    pub reuse_addr: bool,
+    /// `SO_REUSEPORT` on the listeners, ignored outside unix systems.
+    pub reuse_port: bool,
+    /// `TCP_NODELAY` on accepted connections.
+    pub nodelay: bool,
+    /// Idle time before TCP keepalive probes are sent on accepted
+    /// connections, `None` to disable them.
+    pub keepalive: Option<Duration>,
+    /// Time given to in-flight requests to complete on shutdown before their
+    /// connections are dropped.
+    pub shutdown_timeout: Duration,
+    /// Time a session is kept without activity, 8 hours by default.
+    pub session_ttl: Duration,
+    /// Open sessions before the least recently used one is closed. `None`
+    /// derives it from the `RLIMIT_NOFILE` soft limit.
+    pub max_sessions: Option<usize>,
+    /// Tagged requests (see `DIDRequest::id`) dispatched at once on a
+    /// connection, further requests are read once one completes.
+    pub max_concurrent_requests: usize,
+    /// Liveness check of `did://` connections, `None` to keep silent
+    /// connections until `session_ttl`.
+    pub heartbeat: Option<Heartbeat>
+}
+
+/// Heartbeats keeping idle sessions alive behind NATs and firewalls: the
+/// client sends a `PING` every `interval` (see `DIDClient::start_heartbeat`)
+/// and a connection missing `max_missed` heartbeats in a row is closed as
+/// `tcp_connection_closed`, on both sides.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub max_missed: u32
+}
+
+impl Heartbeat {
+    /// Shortest period between two heartbeats, whatever `interval` is.
+    pub const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+    pub fn new(interval: Duration, max_missed: u32) -> Self {
+        Heartbeat { interval, max_missed }
+    }
+
+    /// `interval`, raised to `MIN_INTERVAL`.
+    pub fn period(&self) -> Duration {
+        self.interval.max(Heartbeat::MIN_INTERVAL)
+    }
+
+    /// Silence after which the peer is considered gone.
+    pub fn timeout(&self) -> Duration {
+        self.period() * self.max_missed.max(1)
+    }
+}
+
+impl Default for Heartbeat {
+    /// A `PING` every 30 seconds, closing after 3 missed.
+    fn default() -> Self {
+        Heartbeat::new(Duration::from_secs(30), 3)
+    }
+}
+
+impl DIDServerConfig {
+    /// Port used by servers and clients when none is given.
+    pub const DEFAULT_PORT: u16 = 5173;
+
+    pub fn new() -> Self {
+        DIDServerConfig {
+            bind_addrs: vec![BindAddr::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST))],
+            port: DIDServerConfig::DEFAULT_PORT,
+            backlog: 1024,
+            reuse_addr: true,
+            reuse_port: false,
+            nodelay: true,
+            keepalive: None,
+            shutdown_timeout: Duration::from_secs(10),
+            session_ttl: Duration::from_secs(8 * 60 * 60),
+            max_sessions: None,
+            max_concurrent_requests: 64,
+            heartbeat: None
+        }
+    }
+
+    /// Replaces the addresses the server listens on. Use `BindAddr::DualStack`
+    /// to accept both IPv4 and IPv6 peers on a single socket.
+    pub fn set_bind_addrs(&mut self, addrs: &[BindAddr]) -> &mut Self {
+        self.bind_addrs = addrs.to_vec();
+        self
+    }
+
+    pub fn set_port(&mut self, port: u16) -> &mut Self {
+        self.port = port;
+        self
+    }
+
+    pub fn set_backlog(&mut self, backlog: i32) -> &mut Self {
+        self.backlog = backlog;
+        self
+    }
+
+    pub fn set_reuse_addr(&mut self, reuse: bool) -> &mut Self {
+        self.reuse_addr = reuse;
+        self
+    }
+
+    pub fn set_reuse_port(&mut self, reuse: bool) -> &mut Self {
+        self.reuse_port = reuse;
+        self
+    }
+
+    pub fn set_nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn set_keepalive(&mut self, keepalive: Option<Duration>) -> &mut Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn set_session_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    pub fn set_max_sessions(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_sessions = max;
+        self
+    }
+
+    pub fn set_max_concurrent_requests(&mut self, max: usize) -> &mut Self {
+        self.max_concurrent_requests = max;
+        self
+    }
+
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) -> &mut Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+}
+
+impl Default for DIDServerConfig {
+    fn default() -> Self {
+        DIDServerConfig::new()
+    }
+}
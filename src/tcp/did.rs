@@ -1,38 +1,144 @@
-use std::str::FromStr;
+use std::{net::IpAddr, str::FromStr};
+use futures::{
+    future::BoxFuture,
+    stream::FuturesUnordered,
+    SinkExt,
+    StreamExt};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-    sync::oneshot::Receiver};
+    io::AsyncWriteExt,
+    sync::oneshot::Receiver,
+    time::{self, Instant}};
+use tokio_util::codec::Framed;
+#[cfg(feature = "metrics")]
+use crate::metrics::request_kind;
 use crate::{
-    error::DIDError,
-    identity::DIDIdentity,
-    req::{reqres::{DIDRequest, DIDResponse},
-    verbs::ReqVerb}};
-use super::listener::StreamHandler;
+    error::{DIDError, DIDErrorKind},
+    req::{
+        body::DIDBody,
+        reqres::{DIDRequest, DIDResponse},
+        verbs::ReqVerb},
+    transport::{BoxTransport, Transport, LOCAL_IP}};
+use super::{
+    codec::{DIDFrame, DIDFrameCodec},
+    listener::{ServerContext, StreamHandler},
+    session::SessionId};
 
 pub(super) struct DIDHandler {
-    latest_req: DIDRequest,
-    sock: TcpStream
+    /// First request of the connection, read to pick the handler.
+    first_req: Option<DIDRequest>,
+    /// IP announced by the first request, the session is bound to it.
+    peer_ip: IpAddr,
+    local_ip: IpAddr,
+    stream: Framed<BoxTransport, DIDFrameCodec>
+}
+
+/// Tagged requests being dispatched on a connection.
+type InFlight<'a> = FuturesUnordered<BoxFuture<'a, DIDResponse>>;
+
+/// Dispatches `req` through the server routes, or refuses it if the session
+/// is bound to another IP. Heartbeats are answered right away. The response
+/// is signed with the server identity.
+async fn respond(
+    req: DIDRequest,
+    ctx: &ServerContext,
+    session: SessionId,
+    peer_ip: IpAddr,
+    local_ip: IpAddr
+) -> DIDResponse {
+    let mut res = if req.verb == ReqVerb::Ping {
+        // Heartbeats keep the connection alive, not the session: they don't
+        // count as activity for `session_ttl`.
+        let res = DIDResponse::ok(&req, DIDBody::new());
+
+        // Answered without being dispatched, in no time.
+        #[cfg(feature = "metrics")]
+        ctx.record(|metrics| {
+            metrics.observe(request_kind(&req), &res.status, Default::default())
+        });
+        res
+    } else if req.ip != peer_ip {
+        // Refused requests neither bind nor refresh the session.
+        DIDResponse::error(&req, &DIDError {
+            kind: DIDErrorKind::DidCheckFailure,
+            source: "DIDHandler::answer".into(),
+            reason: format!("IP mismatch, session bound to {}", peer_ip)
+        })
+    } else {
+        let touched = ctx.sessions().touch(session, &req.did);
+
+        match touched {
+            Ok(()) => ctx.dispatch(req).await,
+            Err(err) => DIDResponse::error(&req, &err)
+        }
+    };
+
+    res.set_responder(ctx.identity.did.clone(), local_ip);
+    res
+}
+
+/// Answers a frame whose `header` doesn't parse with `err`, so the peer
+/// isn't left waiting for a response.
+pub(super) async fn refuse(
+    stream: &mut Framed<BoxTransport, DIDFrameCodec>,
+    ctx: &ServerContext,
+    header: &str,
+    err: &DIDError
+) -> Result<(), DIDError> {
+    let local_ip = stream.get_ref().local_ip().unwrap_or(LOCAL_IP);
+    let mut res = DIDResponse::malformed(header, err);
+
+    res.set_responder(ctx.identity.did.clone(), local_ip);
+    stream.send(res.to_frame()?).await
 }
 
 impl DIDHandler {
-    fn process_latest_request<'p>(
-        req: &'p DIDRequest,
-        identity: &'p DIDIdentity
-    ) -> DIDResponse<'p> {
-        DIDResponse {
-            from_req: req,
-            with_identity: identity,
-            content: "OK".into()
+    /// Handler of a connection whose first request is `first_req`.
+    pub(super) fn new(
+        first_req: DIDRequest,
+        stream: Framed<BoxTransport, DIDFrameCodec>
+    ) -> Self {
+        let peer_ip = first_req.ip;
+        let local_ip = stream.get_ref().local_ip().unwrap_or(LOCAL_IP);
+
+        Self { first_req: Some(first_req), peer_ip, local_ip, stream }
+    }
+
+    /// Answers an untagged request right away, so untagged requests are
+    /// answered in order, while a tagged one joins `in_flight` and is
+    /// answered once it completes.
+    async fn answer<'a>(
+        &mut self,
+        req: DIDRequest,
+        ctx: &'a ServerContext,
+        session: SessionId,
+        in_flight: &mut InFlight<'a>
+    ) -> Result<(), DIDError> {
+        let tagged = req.id.is_some();
+        let res = respond(req, ctx, session, self.peer_ip, self.local_ip);
+
+        if tagged {
+            in_flight.push(Box::pin(res));
+            return Ok(());
+        }
+        self.stream.send(res.await.to_frame()?).await
+    }
+
+    /// Answers every request of `in_flight`.
+    async fn drain(&mut self, in_flight: &mut InFlight<'_>) -> Result<(), DIDError> {
+        while let Some(res) = in_flight.next().await {
+            self.stream.send(res.to_frame()?).await?;
         }
+        Ok(())
     }
 }
 
 impl<'h> StreamHandler<'h> for DIDHandler {
     type Method = Result<ReqVerb, DIDError>;
+    type Codec = DIDFrameCodec;
+    type Frame = DIDFrame;
 
     fn parse_req_header(header: &'h str) -> Vec<&'h str> {
-        header.split(",").into_iter().collect()
+        header.split(",").collect()
     }
 
     fn get_header_method(header: &'h str) -> Self::Method {
@@ -41,69 +147,106 @@ impl<'h> StreamHandler<'h> for DIDHandler {
         ReqVerb::from_str(items[0])
     }
 
-    /// When dealing with a DID TCP stream. This function receives a channel
-    /// receiver to receive messages from the main thread to end when the port
-    /// should be allocated to a new connection.
+    /// When dealing with a DID TCP stream. Requests are read one frame at a
+    /// time and untagged ones are answered in order, so a peer may pipeline
+    /// several requests without waiting for their responses. Tagged requests
+    /// are dispatched concurrently, at most `max_concurrent_requests` at once
+    /// (see `DIDServerConfig`), and answered as they complete. Requests whose
+    /// header doesn't parse are answered with `malformed_request`.
+    ///
+    /// With a `heartbeat` configured, a connection silent for longer than
+    /// `Heartbeat::timeout` while none of its requests is pending is evicted
+    /// from the session cache and reported as `tcp_connection_closed`.
+    ///
+    /// This function receives a channel receiver to receive messages from the
+    /// main thread to end when the port should be allocated to a new
+    /// connection.
     async fn handle_stream(
-        &mut self, identity: DIDIdentity, mut rx: Receiver<u8>
+        &mut self, ctx: ServerContext, session: SessionId, rx: &mut Receiver<u8>
     ) -> Result<(), DIDError> {
-        let socket = &mut self.sock;
-        let mut req_str = String::new();
-        let res = DIDHandler::process_latest_request(
-            &self.latest_req, &identity
-        );
+        let max_in_flight = ctx.config.max_concurrent_requests.max(1);
+        let mut in_flight = InFlight::new();
+        let silence = ctx.config.heartbeat.map(|heartbeat| heartbeat.timeout());
+        let max_missed = ctx.config.heartbeat.map_or(0, |heartbeat| {
+            heartbeat.max_missed
+        });
 
-        socket.writable().await.unwrap();
-        socket.write_all(res.to_string().as_bytes()).await.unwrap();
+        if let Some(req) = self.first_req.take() {
+            self.answer(req, &ctx, session, &mut in_flight).await?;
+        }
+
+        // Silence is only counted while the peer has nothing to wait for.
+        let liveness = time::sleep(silence.unwrap_or_default());
+
+        tokio::pin!(liveness);
 
         loop {
             // If we receive something from the oneshot, we know we have to
-            // close the socket to free the associated port.
-            if let Ok(_) = rx.try_recv() {
-                socket.shutdown().await.unwrap();
-                return Ok(());
-            }
+            // close the socket to free the associated port. Requests being
+            // processed are not interrupted.
+            let frame = tokio::select! {
+                _ = &mut *rx => {
+                    self.drain(&mut in_flight).await?;
+                    self.stream.get_mut().shutdown().await?;
+                    return Ok(());
+                },
+                Some(res) = in_flight.next() => {
+                    self.stream.send(res.to_frame()?).await?;
 
-            socket.readable().await.unwrap();
-            socket.read_to_string(&mut req_str).await.unwrap();
+                    if let Some(silence) = silence
+                        && in_flight.is_empty() {
+                        liveness.as_mut().reset(Instant::now() + silence);
+                    }
+                    continue;
+                },
+                _ = &mut liveness, if silence.is_some() && in_flight.is_empty() => {
+                    self.drain(&mut in_flight).await?;
+                    ctx.sessions().remove(session);
+                    #[cfg(feature = "metrics")]
+                    ctx.record(|metrics| metrics.heartbeat_evictions.inc());
+                    let _ = self.stream.get_mut().shutdown().await;
 
-            if req_str.is_empty() {
-                continue
-            }
+                    return Err(DIDError {
+                        kind: DIDErrorKind::TcpConnectionClosed,
+                        source: "DIDHandler::handle_stream".into(),
+                        reason: format!(
+                            "{} missed {max_missed} heartbeats", self.peer_ip
+                        )
+                    });
+                },
+                frame = self.stream.next(), if in_flight.len() < max_in_flight => {
+                    frame
+                }
+            };
+            let Some(frame) = frame else {
+                // The peer may only have closed its writing side.
+                return self.drain(&mut in_flight).await;
+            };
 
-            if let Ok(req) = DIDRequest::from_str(&req_str) {
-                if req.ip != self.latest_req.ip {
-                    error!("{}: {}", self.latest_req.ip, "IP mismatch");
-                } else {
-                    let res = DIDHandler::process_latest_request(
-                        &self.latest_req, &identity
-                    );
-
-                    socket.writable().await.unwrap();
-                    socket.write_all(res.to_string().as_bytes()).await.unwrap();
-                    self.latest_req = req;
+            // A framing error leaves the stream out of sync, while a bad
+            // header only fails its own request.
+            let frame = frame?;
+            let header = frame.header.clone();
+
+            match DIDRequest::try_from(frame) {
+                Ok(req) => {
+                    self.answer(req, &ctx, session, &mut in_flight).await?
+                },
+                Err(err) => {
+                    error!("{}: {}", self.peer_ip, err);
+                    refuse(&mut self.stream, &ctx, &header, &err).await?
                 }
-            } else {
-                error!(
-                    "{}: {}",
-                    self.latest_req.ip,
-                    "Failed to parse request content"
-                );
+            }
+            if let Some(silence) = silence {
+                liveness.as_mut().reset(Instant::now() + silence);
             }
         }
     }
 
     fn from_req_and_stream(
-        str_req: String,
-        sock: TcpStream
+        frame: DIDFrame,
+        stream: Framed<BoxTransport, DIDFrameCodec>
     ) -> Result<Self, DIDError> {
-        if let Ok(latest_req) = DIDRequest::from_str(&str_req) {
-            return Ok(Self { latest_req, sock });
-        }
-
-        Err(DIDError {
-            source: "DIDHandler::handle_stream".into(),
-            reason: "Failed to parse request content".into()
-        })
+        Ok(DIDHandler::new(DIDRequest::try_from(frame)?, stream))
     }
 }
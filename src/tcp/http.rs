@@ -0,0 +1,454 @@
+use std::{net::IpAddr, str::{self, FromStr}};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::oneshot::Receiver;
+use tokio_util::codec::{Decoder, Encoder, Framed, FramedParts};
+use crate::{
+    error::{DIDError, DIDErrorKind, ReqField},
+    req::{
+        reqres::{DIDHeader, DIDRequest, DIDStatus},
+        uri::DIDAddress,
+        verbs::ReqVerb},
+    transport::{BoxTransport, Transport, LOCAL_IP}};
+use super::{
+    codec::{DIDFrame, DIDFrameCodec},
+    listener::{ServerContext, StreamHandler},
+    session::SessionId};
+
+/// Upper bound for the request line and headers.
+const MAX_HEAD_LEN: usize = 8 * 1024;
+/// Upper bound for a request body.
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+/// Protocol name of the `Upgrade` header switching a connection to `did://`
+/// framing.
+pub(crate) const UPGRADE_PROTOCOL: &str = "did";
+
+/// Verbs HTTP clients may send with `DID-Verb`. `PREFLIGHT` needs a
+/// `did://` session, `#DATA` is reserved to the protocol and `PING` to
+/// `did://` heartbeats.
+const HTTP_VERBS: &[ReqVerb] = &[ReqVerb::Data, ReqVerb::Where, ReqVerb::WhereStorage];
+
+const METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "PATCH", "OPTIONS", "CONNECT",
+    "TRACE"
+];
+
+/// An HTTP/1.x request received on the DID port.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Bytes
+}
+
+impl HttpRequest {
+    /// Value of the header `name`, case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the request asks to switch the connection to `did://`
+    /// framing, with `Connection: Upgrade` and `Upgrade: did`.
+    pub fn is_did_upgrade(&self) -> bool {
+        let connection = self.header("connection").unwrap_or_default();
+        let upgrade = self.header("upgrade").unwrap_or_default();
+
+        connection.split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+            && upgrade.split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case(UPGRADE_PROTOCOL))
+    }
+
+    /// HTTP/1.1 connections are persistent unless `Connection: close` is
+    /// given, HTTP/1.0 ones are closed after the response.
+    pub fn keep_alive(&self) -> bool {
+        match self.header("connection") {
+            Some(value) => !value.eq_ignore_ascii_case("close"),
+            None => self.version == "HTTP/1.1"
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Bytes,
+    /// Answers a `HEAD` request: the headers describe `body`, which is not
+    /// sent.
+    pub head_only: bool
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: impl Into<Bytes>) -> Self {
+        HttpResponse {
+            status,
+            headers: vec![],
+            body: body.into(),
+            head_only: false
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sends the headers only, when answering a `HEAD` request.
+    pub fn head_only(mut self, head_only: bool) -> Self {
+        self.head_only = head_only;
+        self
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            101 => "Switching Protocols",
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            501 => "Not Implemented",
+            _ => "Internal Server Error"
+        }
+    }
+}
+
+/// Server side HTTP/1.x codec, decoding requests and encoding responses.
+/// Bodies are delimited by `Content-Length`; chunked bodies are refused.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HttpCodec;
+
+impl HttpCodec {
+    fn error(field: ReqField, reason: &str) -> DIDError {
+        DIDError {
+            kind: DIDErrorKind::MalformedRequest(field),
+            source: "HttpCodec::decode".into(),
+            reason: reason.into()
+        }
+    }
+
+    fn parse_head(head: &str) -> Result<HttpRequest, DIDError> {
+        let mut lines = head.split("\r\n");
+        let request_line = lines.next().unwrap_or_default();
+        let [method, path, version] = request_line.split(' ')
+            .collect::<Vec<&str>>()[..]
+        else {
+            return Err(Self::error(ReqField::Header, "invalid request line"));
+        };
+
+        if !METHODS.contains(&method) || !version.starts_with("HTTP/1.") {
+            return Err(Self::error(ReqField::Header, "invalid request line"));
+        }
+
+        let headers = lines
+            .filter(|line| !line.is_empty())
+            .map(|line| match line.split_once(':') {
+                Some((key, value)) => {
+                    Ok((key.trim().to_string(), value.trim().to_string()))
+                },
+                None => Err(Self::error(ReqField::Header, "invalid header"))
+            })
+            .collect::<Result<Vec<(String, String)>, DIDError>>()?;
+
+        Ok(HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            version: version.to_string(),
+            headers,
+            body: Bytes::new()
+        })
+    }
+}
+
+impl Decoder for HttpCodec {
+    type Item = HttpRequest;
+    type Error = DIDError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<HttpRequest>, DIDError> {
+        let Some(head_len) = src.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if src.len() > MAX_HEAD_LEN {
+                return Err(Self::error(ReqField::Header, "header too long"));
+            }
+            return Ok(None);
+        };
+
+        if head_len > MAX_HEAD_LEN {
+            return Err(Self::error(ReqField::Header, "header too long"));
+        }
+
+        let head = str::from_utf8(&src[..head_len])
+            .map_err(|_| Self::error(ReqField::Header, "header is not valid UTF-8"))?;
+        let mut req = HttpCodec::parse_head(head)?;
+
+        if req.header("transfer-encoding").is_some() {
+            return Err(Self::error(ReqField::Size, "chunked bodies are not supported"));
+        }
+
+        let size = match req.header("content-length") {
+            Some(size) => size.parse::<usize>()
+                .map_err(|_| Self::error(ReqField::Size, "invalid Content-Length"))?,
+            None => 0
+        };
+
+        if size > MAX_BODY_LEN {
+            return Err(Self::error(ReqField::Size, "body too long"));
+        }
+
+        let request_len = head_len + 4 + size;
+
+        if src.len() < request_len {
+            src.reserve(request_len - src.len());
+            return Ok(None);
+        }
+
+        req.body = src.split_to(request_len).freeze().slice(head_len + 4..);
+        Ok(Some(req))
+    }
+}
+
+impl Encoder<HttpResponse> for HttpCodec {
+    type Error = DIDError;
+
+    fn encode(&mut self, item: HttpResponse, dst: &mut BytesMut) -> Result<(), DIDError> {
+        let head = format!("HTTP/1.1 {} {}\r\n", item.status, item.reason());
+
+        dst.put_slice(head.as_bytes());
+        for (key, value) in &item.headers {
+            dst.put_slice(format!("{key}: {value}\r\n").as_bytes());
+        }
+        // Informational responses have no body.
+        if item.status >= 200 {
+            dst.put_slice(format!("Content-Length: {}\r\n", item.body.len()).as_bytes());
+        }
+        dst.put_slice(b"\r\n");
+        if !item.head_only {
+            dst.put_slice(&item.body);
+        }
+        Ok(())
+    }
+}
+
+/// Serves the routes of the server over HTTP/1.1 on the DID port. A request
+/// `<METHOD> <path> HTTP/1.1` is dispatched as a DID request:
+/// - its verb is given by the `DID-Verb` header among `HTTP_VERBS`, `DATA`
+///   by default,
+/// - its sender DID by the `DID` header, which is required but not verified:
+///   HTTP requests never bind their session to it,
+/// - its URL is `did://<server DID><path>` and its IP the peer address.
+///
+/// The DID status of the response is given by the `DID-Status` header and
+/// mapped to the HTTP status.
+///
+/// A request with `Connection: Upgrade` and `Upgrade: did` is answered with
+/// `101 Switching Protocols` and the connection continues with `did://`
+/// framing, as DNS DIDs do once reached over HTTPS (see `into_upgraded`).
+pub(super) struct HttpHandler {
+    first_req: Option<HttpRequest>,
+    peer_ip: IpAddr,
+    stream: Framed<BoxTransport, HttpCodec>,
+    upgraded: bool
+}
+
+impl HttpHandler {
+    fn to_did_request(
+        req: HttpRequest,
+        ctx: &ServerContext,
+        peer_ip: IpAddr
+    ) -> Result<DIDRequest, DIDError> {
+        let verb = match req.header("did-verb") {
+            Some(verb) => ReqVerb::from_str(verb)?,
+            None => ReqVerb::Data
+        };
+
+        if !HTTP_VERBS.contains(&verb) {
+            return Err(DIDError {
+                kind: DIDErrorKind::MalformedRequest(ReqField::Verb),
+                source: "HttpHandler::to_did_request".into(),
+                reason: format!("{verb} can't be sent over HTTP")
+            });
+        }
+        let did = req.header("did").ok_or_else(|| DIDError {
+            kind: DIDErrorKind::MalformedRequest(ReqField::Did),
+            source: "HttpHandler::to_did_request".into(),
+            reason: "missing DID header".into()
+        })?;
+        let url = DIDAddress::from_str(
+            &format!("did://{}{}", ctx.identity.did, req.path)
+        )?;
+        let header = DIDHeader::new(verb, Some(url), did.to_string(), peer_ip);
+
+        Ok(DIDRequest::from_header(header, req.body.into()))
+    }
+
+    /// The metrics of the server, for a `GET` on the path they are served
+    /// on.
+    #[cfg(feature = "metrics")]
+    fn metrics_response(
+        req: &HttpRequest,
+        ctx: &ServerContext
+    ) -> Option<HttpResponse> {
+        let path = ctx.metrics_path.as_deref()?;
+        let metrics = ctx.metrics.as_ref()?;
+
+        // Scrapers may add query parameters, such as a cache buster.
+        let target = req.path.split_once('?')
+            .map_or(req.path.as_str(), |(target, _)| target);
+
+        if req.method != "GET" || target != path {
+            return None;
+        }
+
+        let res = HttpResponse::new(200, metrics.gather())
+            .header("Content-Type", "text/plain; version=0.0.4");
+
+        Some(res)
+    }
+
+    fn status_code(status: &DIDStatus) -> u16 {
+        match status {
+            DIDStatus::Ok => 200,
+            DIDStatus::NotFound => 404,
+            DIDStatus::Err(DIDErrorKind::MalformedRequest(_)) => 400,
+            DIDStatus::Err(DIDErrorKind::DidCheckFailure) => 403,
+            DIDStatus::Err(_) => 500
+        }
+    }
+
+    /// The connection, framed for `did://`, if the peer switched to it. Bytes
+    /// the peer sent right after the upgrade request are kept.
+    pub fn into_upgraded(self) -> Option<Framed<BoxTransport, DIDFrameCodec>> {
+        if !self.upgraded {
+            return None;
+        }
+
+        let parts = self.stream.into_parts();
+        let mut did_parts = FramedParts::new::<DIDFrame>(parts.io, DIDFrameCodec::new());
+
+        did_parts.read_buf = parts.read_buf;
+        Some(Framed::from_parts(did_parts))
+    }
+
+    /// Answers `req`, returning whether the connection should stay open.
+    async fn answer(
+        &mut self,
+        req: HttpRequest,
+        ctx: &ServerContext,
+        session: SessionId
+    ) -> Result<bool, DIDError> {
+        let keep_alive = req.keep_alive();
+        let head_only = req.method == "HEAD";
+
+        #[cfg(feature = "metrics")]
+        if let Some(res) = HttpHandler::metrics_response(&req, ctx) {
+            self.stream.send(res.head_only(head_only)).await?;
+            return Ok(keep_alive);
+        }
+
+        let res = match HttpHandler::to_did_request(req, ctx, self.peer_ip) {
+            Ok(req) => {
+                // The DID header is not verified, it doesn't bind the
+                // session.
+                ctx.sessions().refresh(session);
+
+                let res = ctx.dispatch(req).await;
+
+                HttpResponse::new(
+                    HttpHandler::status_code(&res.status),
+                    res.body.into_bytes()
+                ).header("DID-Status", res.status)
+            },
+            Err(err) => HttpResponse::new(400, err.reason)
+                .header("DID-Status", DIDStatus::Err(err.kind))
+        };
+        let res = res.header("DID", &ctx.identity.did).head_only(head_only);
+
+        self.stream.send(res).await?;
+        Ok(keep_alive)
+    }
+}
+
+impl<'h> StreamHandler<'h> for HttpHandler {
+    type Method = Option<&'h str>;
+    type Codec = HttpCodec;
+    type Frame = HttpRequest;
+
+    fn parse_req_header(header: &'h str) -> Vec<&'h str> {
+        header.trim_end().split(' ').collect()
+    }
+
+    fn get_header_method(header: &'h str) -> Self::Method {
+        match HttpHandler::parse_req_header(header)[..] {
+            [method, _, version] if METHODS.contains(&method)
+                && version.starts_with("HTTP/1.") => Some(method),
+            _ => None
+        }
+    }
+
+    /// Answers the requests of the connection in order, until the peer asks
+    /// to close it, switches to `did://` or the session is closed.
+    async fn handle_stream(
+        &mut self, ctx: ServerContext, session: SessionId, rx: &mut Receiver<u8>
+    ) -> Result<(), DIDError> {
+        let mut next = self.first_req.take();
+
+        loop {
+            let req = match next.take() {
+                Some(req) => req,
+                None => {
+                    let req = tokio::select! {
+                        _ = &mut *rx => return Ok(()),
+                        req = self.stream.next() => req
+                    };
+
+                    match req {
+                        Some(Ok(req)) => req,
+                        Some(Err(err)) => {
+                            let res = HttpResponse::new(400, err.reason.clone())
+                                .header("Connection", "close");
+
+                            self.stream.send(res).await?;
+                            return Err(err);
+                        },
+                        None => return Ok(())
+                    }
+                }
+            };
+
+            if ctx.did_enabled && req.is_did_upgrade() {
+                let res = HttpResponse::new(101, Bytes::new())
+                    .header("Connection", "Upgrade")
+                    .header("Upgrade", UPGRADE_PROTOCOL)
+                    .header("DID", &ctx.identity.did);
+
+                self.stream.send(res).await?;
+                self.upgraded = true;
+                return Ok(());
+            }
+
+            if !self.answer(req, &ctx, session).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn from_req_and_stream(
+        frame: HttpRequest,
+        stream: Framed<BoxTransport, HttpCodec>
+    ) -> Result<Self, DIDError> {
+        let peer_ip = stream.get_ref().peer_ip().unwrap_or(LOCAL_IP);
+
+        Ok(HttpHandler {
+            first_req: Some(frame),
+            peer_ip, stream,
+            upgraded: false
+        })
+    }
+}
+
@@ -1,109 +1,514 @@
 use std::{
-    time::{SystemTime, UNIX_EPOCH}};
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex}};
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
 use rlimit::{getrlimit, Resource};
-use tokio::{io,
-    net::{TcpListener, TcpStream},
-    sync::oneshot::{self, Receiver, Sender}};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWriteExt, DuplexStream},
+    net::TcpListener,
+    sync::{mpsc, oneshot::Receiver, watch},
+    task::{JoinHandle, JoinSet},
+    time::{self, Duration}};
+use tokio_util::codec::{Decoder, Encoder, Framed, FramedParts};
+#[cfg(feature = "metrics")]
+use crate::metrics::{request_kind, Metrics};
 use crate::{
     error::DIDError,
     identity::DIDIdentity,
-    tcp::stream::read_expected_size};
-use super::did::DIDHandler;
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        router::Router},
+    transport::{Acceptor, BoxTransport, Transport}};
+use super::{
+    codec::DIDFrameCodec,
+    config::DIDServerConfig,
+    did::{refuse, DIDHandler},
+    http::{HttpCodec, HttpHandler, HttpResponse},
+    session::{SessionCache, SessionId}};
 
-pub(super) struct SockCacheEntry {
-    channel_sender: Sender<u8>,
-    created_at: u128
+/// Upper bound for the first line of a connection, read to pick its
+/// protocol.
+const MAX_FIRST_LINE_LEN: usize = 8 * 1024;
+
+/// Address a `DIDServer` listens on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BindAddr {
+    /// A single IPv4 or IPv6 address. IPv6 sockets only accept IPv6 peers.
+    Ip(IpAddr),
+    /// `[::]`, accepting both IPv6 peers and IPv4 peers (as IPv4-mapped IPv6
+    /// addresses) on a single socket.
+    DualStack
+}
+
+/// Everything a connection task needs from the `DIDServer`.
+#[derive(Clone)]
+pub(crate) struct ServerContext {
+    pub identity: DIDIdentity,
+    pub router: Arc<Router>,
+    pub config: Arc<DIDServerConfig>,
+    pub sessions: Arc<Mutex<SessionCache>>,
+    pub http_enabled: bool,
+    pub did_enabled: bool,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<Metrics>,
+    /// HTTP path the metrics are served on, see `DIDServer::serve_metrics`.
+    #[cfg(feature = "metrics")]
+    pub metrics_path: Option<String>
+}
+
+impl ServerContext {
+    pub fn new(
+        identity: DIDIdentity,
+        router: Router,
+        config: DIDServerConfig,
+        http_enabled: bool,
+        did_enabled: bool
+    ) -> Self {
+        // To avoid issues with TCP connections failing to open, we get the
+        // maximum number of files (incl. sockets) the app is allowed to get
+        // open and keep some room for listeners, logs and outgoing
+        // connections.
+        let capacity = config.max_sessions.unwrap_or_else(|| {
+            let (max_files, _) = getrlimit(Resource::NOFILE)
+                .expect("Failed to get NOFILE");
+
+            usize::try_from(max_files).unwrap_or(usize::MAX).saturating_sub(64)
+        });
+        let sessions = SessionCache::new(capacity, config.session_ttl);
+
+        ServerContext {
+            identity,
+            router: Arc::new(router),
+            config: Arc::new(config),
+            sessions: Arc::new(Mutex::new(sessions)),
+            http_enabled,
+            did_enabled,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "metrics")]
+            metrics_path: None
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Metrics, path: Option<String>) -> Self {
+        self.metrics = Some(metrics);
+        self.metrics_path = path;
+        self
+    }
+
+    /// Updates the metrics of the server, if any.
+    #[cfg(feature = "metrics")]
+    pub fn record(&self, update: impl FnOnce(&Metrics)) {
+        if let Some(metrics) = &self.metrics {
+            update(metrics);
+        }
+    }
+
+    /// Dispatches `req` through the server routes.
+    pub async fn dispatch(&self, req: DIDRequest) -> DIDResponse {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            let kind = request_kind(&req);
+            let started = time::Instant::now();
+            let res = self.router.dispatch(req).await;
+
+            metrics.observe(kind, &res.status, started.elapsed());
+            return res;
+        }
+
+        self.router.dispatch(req).await
+    }
+
+    /// Locks the session cache, which is never left inconsistent by a panic.
+    pub fn sessions(&self) -> std::sync::MutexGuard<'_, SessionCache> {
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner())
+    }
 }
 
+/// A protocol served on the DID port, see `redirect_to_handler`.
 pub(super) trait StreamHandler<'h>: Sized {
     type Method;
+    /// Codec framing the requests of the protocol.
+    type Codec;
+    /// A request as decoded by `Codec`.
+    type Frame;
 
     fn parse_req_header(header: &'h str) -> Vec<&'h str>;
+    /// Recognizes the first line of a connection.
     fn get_header_method(header: &'h str) -> Self::Method;
     async fn handle_stream(
-        &mut self, identity: DIDIdentity, rx: Receiver<u8>
+        &mut self, ctx: ServerContext, session: SessionId, rx: &mut Receiver<u8>
     ) -> Result<(), DIDError>;
     fn from_req_and_stream(
-        str_req: String, sock: TcpStream
+        frame: Self::Frame, stream: Framed<BoxTransport, Self::Codec>
     ) -> Result<Self, DIDError>;
 }
 
+/// Reads the first line of `sock` into `buf`, without consuming anything the
+/// handler will need. Returns `None` if the peer closes the connection first.
+async fn read_first_line(
+    sock: &mut BoxTransport,
+    buf: &mut BytesMut
+) -> io::Result<Option<usize>> {
+    loop {
+        if let Some(end) = buf.iter().position(|b| *b == b'\n') {
+            return Ok(Some(end));
+        }
+        if buf.len() > MAX_FIRST_LINE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "first line too long"
+            ));
+        }
+        if sock.read_buf(buf).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
+
+/// Frames `sock` with `codec`, starting with the bytes already read.
+fn framed<C, I>(
+    sock: BoxTransport,
+    codec: C,
+    buf: BytesMut
+) -> Framed<BoxTransport, C>
+where
+    C: Encoder<I>
+{
+    let mut parts = FramedParts::new::<I>(sock, codec);
+
+    parts.read_buf = buf;
+    Framed::from_parts(parts)
+}
+
+/// Reads the first request of `stream`, unless the session is closed first.
+async fn first_frame<C: Decoder<Error = DIDError>>(
+    stream: &mut Framed<BoxTransport, C>,
+    rx: &mut Receiver<u8>
+) -> Option<Result<C::Item, DIDError>> {
+    tokio::select! {
+        frame = stream.next() => frame,
+        _ = &mut *rx => None
+    }
+}
+
 /// Will read the request and use the appropriate handler to interact with it.
 ///
 /// All requests with the first line's items separated by "," and starting
-/// with a verb will be handled by `did_stream_handler`.
+/// with a verb will be handled by `DIDHandler`.
 /// 
 /// All requests with the first line's items separated by " " and starting
-/// with a HTTP method will be handled by `http_stream_handler`.
+/// with a HTTP method will be handled by `HttpHandler`.
+///
+/// Each protocol is only served when enabled on the `DIDServer`.
 async fn redirect_to_handler(
-    mut sock: TcpStream,
-    identity: DIDIdentity,
-    rx: Receiver<u8>
+    mut sock: BoxTransport,
+    ctx: &ServerContext,
+    session: SessionId,
+    mut rx: Receiver<u8>
 ) {
-    let content = read_expected_size(&mut sock).await;
-    if DIDHandler::get_header_method(&content).is_ok() {
-        let handler = DIDHandler::from_req_and_stream(content, sock);
-
-        if let Err(err) = handler {
-            error!("{}", err.to_string());
-        } else {
-            handler.unwrap().handle_stream(identity, rx).await.unwrap();
+    let mut buf = BytesMut::new();
+    let line_end = tokio::select! {
+        end = read_first_line(&mut sock, &mut buf) => end,
+        _ = &mut rx => return
+    };
+    let first_line = match line_end {
+        Ok(Some(end)) => String::from_utf8_lossy(&buf[..end]).into_owned(),
+        Ok(None) => return,
+        Err(err) => {
+            error!("{err}");
+            return;
         }
+    };
+
+    if ctx.did_enabled && DIDHandler::get_header_method(&first_line).is_ok() {
+        let stream = framed(sock, DIDFrameCodec::new(), buf);
+
+        serve_did(stream, ctx, session, &mut rx).await;
+    } else if ctx.http_enabled
+        && HttpHandler::get_header_method(&first_line).is_some() {
+        let mut stream = framed(sock, HttpCodec, buf);
+        let req = match first_frame(&mut stream, &mut rx).await {
+            Some(Ok(req)) => req,
+            Some(Err(err)) => {
+                let res = HttpResponse::new(400, err.reason.clone())
+                    .header("Connection", "close");
+                let _ = stream.send(res).await;
+
+                error!("{err}");
+                return;
+            },
+            None => return
+        };
+
+        match HttpHandler::from_req_and_stream(req, stream) {
+            Ok(mut handler) => {
+                let res = handler.handle_stream(ctx.clone(), session, &mut rx);
+
+                if let Err(err) = res.await {
+                    error!("{err}");
+                    return;
+                }
+                if let Some(stream) = handler.into_upgraded() {
+                    serve_did(stream, ctx, session, &mut rx).await;
+                }
+            },
+            Err(err) => error!("{err}")
+        }
+    } else {
+        info!("Unsupported protocol: {first_line:?}");
     }
 }
 
-/// Will setup a TCP server that will handle both DID and HTTP requests.
-pub async fn tcp_server(port: usize, identity: DIDIdentity) -> io::Result<()> {
-    let listener = TcpListener::bind(format!("127.0.0.1:{port}")).await?;
-    // The sock_list is used to store all active TCP connections and manage
-    // them according to the current node needs. As defined in the 
-    // documentation starting at line 194: Session caching.
-    let mut sock_list: Vec<SockCacheEntry> = vec![];
+/// Serves `did://` requests on `stream`, from its first frame.
+async fn serve_did(
+    mut stream: Framed<BoxTransport, DIDFrameCodec>,
+    ctx: &ServerContext,
+    session: SessionId,
+    rx: &mut Receiver<u8>
+) {
+    let frame = match first_frame(&mut stream, rx).await {
+        Some(Ok(frame)) => frame,
+        Some(Err(err)) => {
+            error!("{err}");
+            return;
+        },
+        None => return
+    };
 
-    let (max_files, _) = getrlimit(Resource::NOFILE)
-        .expect("Failed to get NOFILE");
+    // Without a first request, the session can't be bound to an IP: the
+    // peer is told why before the connection is closed.
+    let header = frame.header.clone();
+    let req = match DIDRequest::try_from(frame) {
+        Ok(req) => req,
+        Err(err) => {
+            error!("{err}");
+            let _ = refuse(&mut stream, ctx, &header, &err).await;
+            let _ = stream.get_mut().shutdown().await;
+            return;
+        }
+    };
+    let mut handler = DIDHandler::new(req, stream);
 
-    // To avoid issues with TCP connections failing to open, we get the maximum
-    // number of files (incl. sockets) the app is allowed to get open and we
-    // keep being at `max - 1` to prevent the system from denying new 
-    // connections.
+    if let Err(err) = handler.handle_stream(ctx.clone(), session, rx).await {
+        error!("{err}");
+    }
+}
 
-    info!("Running on port {port}");
-    loop {
-        match listener.accept().await {
-            Ok((sock, addr)) => {
-                if sock_list.len() == (max_files - 1).try_into().unwrap() {
-                    let mut oldest_timestamp = 0;
-                    
-                    sock_list.iter().for_each(|sock| {
-                        oldest_timestamp = sock.created_at;
-                    });
-                    
-                    let sock_pos = sock_list.iter()
-                        .position(|s| { s.created_at == oldest_timestamp })
-                        .unwrap();
-                    let sock = sock_list.remove(sock_pos);
-
-                    sock.channel_sender.send(0).unwrap();
-                }
-                
-                let (tx, rx): (Sender<u8>, Receiver<u8>) = oneshot::channel();
-                let cache_instance = SockCacheEntry {
-                    channel_sender: tx,
-                    created_at: SystemTime::now().duration_since(UNIX_EPOCH)
-                        .unwrap().as_millis()
+fn bind_addr(
+    addr: BindAddr,
+    config: &DIDServerConfig
+) -> io::Result<TcpListener> {
+    let (ip, only_v6) = match addr {
+        BindAddr::Ip(ip) => (ip, true),
+        BindAddr::DualStack => (IpAddr::V6(Ipv6Addr::UNSPECIFIED), false)
+    };
+    let addr = SocketAddr::new(ip, config.port);
+    let socket = Socket::new(
+        Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP)
+    )?;
+
+    if addr.is_ipv6() {
+        socket.set_only_v6(only_v6)?;
+    }
+    socket.set_reuse_address(config.reuse_addr)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(config.reuse_port)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(config.backlog)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Binds a listener on every address of `config`.
+pub(crate) fn bind(config: &DIDServerConfig) -> io::Result<Vec<TcpListener>> {
+    config.bind_addrs.iter()
+        .map(|addr| bind_addr(*addr, config))
+        .collect()
+}
+
+/// Handle of a running `DIDServer`, returned by `DIDServer::start`.
+pub struct ServerHandle {
+    local_addrs: Vec<SocketAddr>,
+    sessions: Arc<Mutex<SessionCache>>,
+    conn_tx: mpsc::Sender<(BoxTransport, String)>,
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<io::Result<()>>
+}
+
+impl ServerHandle {
+    /// Address of the first IP listener, with the actual port when the
+    /// server was bound on port `0`. Panics if the server has no IP listener.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Number of open sessions, see "Session caching" in the README.
+    pub fn session_count(&self) -> usize {
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner()).len()
+    }
+
+    /// Serves a connection established outside of the server's listeners,
+    /// such as one end of an in-memory duplex stream.
+    pub async fn serve(&self, io: impl Transport) -> io::Result<()> {
+        let conn = (Box::new(io) as BoxTransport, "in-memory peer".to_string());
+
+        self.conn_tx.send(conn).await
+            .map_err(|_| io::Error::from(io::ErrorKind::NotConnected))
+    }
+
+    /// Opens an in-memory connection to the server, without any socket.
+    /// Pass the returned stream to `DIDClient::from_transport`.
+    pub async fn duplex(&self) -> io::Result<DuplexStream> {
+        let (client, server) = io::duplex(64 * 1024);
+
+        self.serve(server).await?;
+        Ok(client)
+    }
+
+    /// Stops accepting connections, asks every cached connection to close
+    /// and waits for in-flight requests, at most `shutdown_timeout` (see
+    /// `DIDServerConfig`).
+    pub async fn shutdown(self) -> io::Result<()> {
+        self.shutdown.send_replace(true);
+        self.wait().await
+    }
+
+    /// Waits for the server to stop.
+    pub async fn wait(self) -> io::Result<()> {
+        self.task.await.unwrap_or_else(|err| Err(io::Error::other(err)))
+    }
+}
+
+/// Will setup a server that will handle both DID and HTTP requests on every
+/// listener of `listeners`, running until the returned handle shuts it down.
+/// A server without listeners only serves the connections given to
+/// `ServerHandle::serve`.
+pub(crate) fn spawn_server(
+    listeners: Vec<Box<dyn Acceptor>>,
+    ctx: ServerContext
+) -> ServerHandle {
+    let local_addrs = listeners.iter()
+        .filter_map(|listener| listener.socket_addr())
+        .collect();
+    let sessions = ctx.sessions.clone();
+    let (conn_tx, conn_rx) = mpsc::channel(64);
+    let (shutdown, shutdown_rx) = watch::channel(false);
+    let task = tokio::spawn(
+        tcp_server(listeners, ctx, conn_tx.clone(), conn_rx, shutdown_rx)
+    );
+
+    ServerHandle { local_addrs, sessions, conn_tx, shutdown, task }
+}
+
+async fn tcp_server(
+    listeners: Vec<Box<dyn Acceptor>>,
+    ctx: ServerContext,
+    conn_tx: mpsc::Sender<(BoxTransport, String)>,
+    mut conn_rx: mpsc::Receiver<(BoxTransport, String)>,
+    mut shutdown: watch::Receiver<bool>
+) -> io::Result<()> {
+
+    // Every listener forwards its connections to the loop below, which owns
+    // the session cache. Listeners are dropped on shutdown.
+    for listener in listeners {
+        let conn_tx = conn_tx.clone();
+        let mut shutdown = shutdown.clone();
+
+        info!("Listening on {}", listener.describe());
+        tokio::spawn(async move {
+            loop {
+                let conn = tokio::select! {
+                    conn = listener.accept() => conn,
+                    _ = shutdown.wait_for(|stop| *stop) => return
                 };
-                let identity = identity.clone();
 
-                sock_list.push(cache_instance);
+                match conn {
+                    Ok(conn) => if conn_tx.send(conn).await.is_err() {
+                        return;
+                    },
+                    Err(e) => error!("Could not accept a connection: {e}")
+                }
+            }
+        });
+    }
+    drop(conn_tx);
+
+    // Sessions are closed after `session_ttl` without activity, checked at
+    // most every minute. The period can't be zero, even with a zero TTL.
+    let mut expiry = time::interval(ctx.config.session_ttl.clamp(
+        Duration::from_millis(10),
+        Duration::from_secs(60)
+    ));
+    let mut connections = JoinSet::new();
 
-                info!("{addr} connected");
-                tokio::spawn(async move {
-                    redirect_to_handler(sock, identity, rx).await;
+    loop {
+        let (sock, addr) = tokio::select! {
+            conn = conn_rx.recv() => match conn {
+                Some(conn) => conn,
+                None => break
+            },
+            Some(_) = connections.join_next(), if !connections.is_empty() => {
+                continue;
+            },
+            _ = expiry.tick() => {
+                let expired = ctx.sessions().expire();
+
+                if expired > 0 {
+                    info!("{expired} sessions expired");
+                }
+                #[cfg(feature = "metrics")]
+                ctx.record(|metrics| {
+                    metrics.sessions_expired.inc_by(expired as u64);
+                    metrics.sessions.set(ctx.sessions().len() as i64);
                 });
+                continue;
             },
-            Err(e) => error!("Could not get TCP stream: {e}")
+            _ = shutdown.wait_for(|stop| *stop) => break
         };
+
+        if let Err(err) = sock.configure(&ctx.config) {
+            error!("{addr}: {err}");
+        }
+
+        // The session cache closes the least recently used session when full.
+        let (session, rx) = ctx.sessions().open();
+        let ctx = ctx.clone();
+
+        #[cfg(feature = "metrics")]
+        ctx.record(|metrics| {
+            metrics.connections.inc();
+            metrics.sessions.set(ctx.sessions().len() as i64);
+        });
+
+        info!("{addr} connected");
+        connections.spawn(async move {
+            redirect_to_handler(sock, &ctx, session, rx).await;
+            ctx.sessions().remove(session);
+            #[cfg(feature = "metrics")]
+            ctx.record(|metrics| {
+                metrics.sessions.set(ctx.sessions().len() as i64);
+            });
+        });
     }
-}
 
+    info!("Shutting down, closing {} connections", connections.len());
+    ctx.sessions().close_all();
+
+    let drained = time::timeout(ctx.config.shutdown_timeout, async {
+        while connections.join_next().await.is_some() {}
+    }).await;
+
+    if drained.is_err() {
+        warn!("Dropping {} connections after timeout", connections.len());
+        connections.shutdown().await;
+    }
+    Ok(())
+}
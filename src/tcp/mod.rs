@@ -1,3 +1,6 @@
 pub mod listener;
-pub mod stream;
+pub mod codec;
+pub mod config;
 pub mod did;
+pub mod http;
+pub mod session;
@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant}};
+use tokio::sync::oneshot::{self, Receiver, Sender};
+use crate::error::{DIDError, DIDErrorKind};
+
+/// Identifier of a connection in the `SessionCache`.
+pub(crate) type SessionId = u64;
+
+struct Session {
+    /// DID of the peer, bound on its first request.
+    did: Option<String>,
+    /// Asks the connection task to close the socket.
+    channel_sender: Sender<u8>,
+    last_activity: Instant
+}
+
+/// Open connections of a server, as described in "Session caching" in the
+/// README: sessions are tied to the DID of their peer, kept for `ttl` without
+/// activity, and the least recently used one is closed to make room once
+/// `capacity` connections are open.
+///
+/// DIDs are not verified until `PREFLIGHT`, so a session never closes
+/// another one on behalf of the DID its peer claims.
+pub(crate) struct SessionCache {
+    sessions: HashMap<SessionId, Session>,
+    next_id: SessionId,
+    capacity: usize,
+    ttl: Duration
+}
+
+impl SessionCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        SessionCache {
+            sessions: HashMap::new(),
+            next_id: 0,
+            capacity: capacity.max(1),
+            ttl
+        }
+    }
+
+    /// Registers a new connection, closing the least recently used one when
+    /// the cache is full. The receiver is signaled when the connection should
+    /// close.
+    pub fn open(&mut self) -> (SessionId, Receiver<u8>) {
+        while self.sessions.len() >= self.capacity {
+            let oldest = self.sessions.iter()
+                .min_by_key(|(_, session)| session.last_activity)
+                .map(|(id, _)| *id);
+
+            match oldest {
+                Some(id) => self.close(id),
+                None => break
+            }
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let id = self.next_id;
+
+        self.next_id += 1;
+        self.sessions.insert(id, Session {
+            did: None,
+            channel_sender: tx,
+            last_activity: Instant::now()
+        });
+        (id, rx)
+    }
+
+    /// Records activity on `id` from `did`. The session is bound to the DID
+    /// of its first request, a request naming another DID is refused as
+    /// `did_check_failure` and doesn't count as activity.
+    pub fn touch(&mut self, id: SessionId, did: &str) -> Result<(), DIDError> {
+        let Some(session) = self.sessions.get_mut(&id) else {
+            return Ok(());
+        };
+
+        match &session.did {
+            Some(bound) if bound != did => return Err(DIDError {
+                kind: DIDErrorKind::DidCheckFailure,
+                source: "SessionCache::touch".into(),
+                reason: format!("DID mismatch, session bound to {bound}")
+            }),
+            Some(_) => {},
+            None => session.did = Some(did.to_string())
+        }
+        session.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Records activity on `id` without binding it to any DID, for peers
+    /// that don't speak `did://`.
+    pub fn refresh(&mut self, id: SessionId) {
+        if let Some(session) = self.sessions.get_mut(&id) {
+            session.last_activity = Instant::now();
+        }
+    }
+
+    /// Forgets `id`, once its connection ended.
+    pub fn remove(&mut self, id: SessionId) {
+        self.take(id);
+    }
+
+    fn take(&mut self, id: SessionId) -> Option<Session> {
+        self.sessions.remove(&id)
+    }
+
+    /// Closes the sessions without activity for longer than the TTL, returning
+    /// how many were closed.
+    pub fn expire(&mut self) -> usize {
+        let expired = self.sessions.iter()
+            .filter(|(_, session)| session.last_activity.elapsed() > self.ttl)
+            .map(|(id, _)| *id)
+            .collect::<Vec<SessionId>>();
+
+        for id in &expired {
+            self.close(*id);
+        }
+        expired.len()
+    }
+
+    /// Closes every session, on shutdown.
+    pub fn close_all(&mut self) {
+        let ids = self.sessions.keys().copied().collect::<Vec<SessionId>>();
+
+        for id in ids {
+            self.close(id);
+        }
+    }
+
+    fn close(&mut self, id: SessionId) {
+        if let Some(session) = self.take(id) {
+            debug!("Closing session {id} of {:?}", session.did);
+            // The connection may already be gone.
+            let _ = session.channel_sender.send(0);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+}
@@ -0,0 +1,130 @@
+//! Byte streams the protocol runs on. Servers and clients only need an
+//! `AsyncRead + AsyncWrite` stream: TCP sockets, Unix domain sockets for local
+//! IPC, and in-memory duplex streams for tests are provided.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use futures::future::BoxFuture;
+use socket2::{SockRef, TcpKeepalive};
+use tokio::{
+    io::{self, AsyncRead, AsyncWrite, DuplexStream},
+    net::{TcpListener, TcpStream}};
+use crate::DIDServerConfig;
+
+/// IP used for the ends of transports without IP.
+pub(crate) const LOCAL_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// A connection carrying DID or HTTP requests.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    /// IP of the local end, `None` for transports without IP such as local
+    /// IPC, where `127.0.0.1` is used instead.
+    fn local_ip(&self) -> Option<IpAddr> {
+        None
+    }
+
+    /// IP of the remote end, see `local_ip`.
+    fn peer_ip(&self) -> Option<IpAddr> {
+        None
+    }
+
+    /// Applies the connection options of `config` to an accepted
+    /// connection.
+    fn configure(&self, _config: &DIDServerConfig) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Transports of different kinds served by the same server.
+pub type BoxTransport = Box<dyn Transport>;
+
+impl Transport for BoxTransport {
+    fn local_ip(&self) -> Option<IpAddr> {
+        (**self).local_ip()
+    }
+
+    fn peer_ip(&self) -> Option<IpAddr> {
+        (**self).peer_ip()
+    }
+
+    fn configure(&self, config: &DIDServerConfig) -> io::Result<()> {
+        (**self).configure(config)
+    }
+}
+
+impl Transport for TcpStream {
+    fn local_ip(&self) -> Option<IpAddr> {
+        Some(self.local_addr().ok()?.ip().to_canonical())
+    }
+
+    fn peer_ip(&self) -> Option<IpAddr> {
+        Some(self.peer_addr().ok()?.ip().to_canonical())
+    }
+
+    fn configure(&self, config: &DIDServerConfig) -> io::Result<()> {
+        self.set_nodelay(config.nodelay)?;
+
+        if let Some(time) = config.keepalive {
+            SockRef::from(self)
+                .set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Transport for tokio::net::UnixStream {}
+
+impl Transport for DuplexStream {}
+
+/// Listener handing connections to a `DIDServer`, see
+/// `DIDServer::start_with_listener`.
+pub trait Acceptor: Send + 'static {
+    /// Waits for a connection, returned with a description of its peer.
+    fn accept(&self) -> BoxFuture<'_, io::Result<(BoxTransport, String)>>;
+
+    /// Address of the listener, for IP listeners.
+    fn socket_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Description of the listener for logs.
+    fn describe(&self) -> String;
+}
+
+impl Acceptor for TcpListener {
+    fn accept(&self) -> BoxFuture<'_, io::Result<(BoxTransport, String)>> {
+        Box::pin(async move {
+            let (sock, addr) = TcpListener::accept(self).await?;
+
+            Ok((Box::new(sock) as BoxTransport, addr.to_string()))
+        })
+    }
+
+    fn socket_addr(&self) -> Option<SocketAddr> {
+        self.local_addr().ok()
+    }
+
+    fn describe(&self) -> String {
+        match self.local_addr() {
+            Ok(addr) => addr.to_string(),
+            Err(err) => err.to_string()
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Acceptor for tokio::net::UnixListener {
+    fn accept(&self) -> BoxFuture<'_, io::Result<(BoxTransport, String)>> {
+        Box::pin(async move {
+            let (sock, _) = tokio::net::UnixListener::accept(self).await?;
+
+            Ok((Box::new(sock) as BoxTransport, "unix peer".to_string()))
+        })
+    }
+
+    fn describe(&self) -> String {
+        match self.local_addr() {
+            Ok(addr) => format!("unix:{:?}", addr.as_pathname()),
+            Err(err) => err.to_string()
+        }
+    }
+}
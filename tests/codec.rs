@@ -0,0 +1,136 @@
+use bytes::{Bytes, BytesMut};
+use proto_did::{
+    error::{DIDErrorKind, ReqField},
+    req::reqres::{DIDHeader, DIDRequest, DIDResponse, DIDStatus},
+    DIDFrame, DIDFrameCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+mod common;
+use common::data;
+
+fn frame(header: &str, body: &str) -> DIDFrame {
+    DIDFrame {
+        header: header.to_string(),
+        body: Bytes::from(body.to_string())
+    }
+}
+
+#[test]
+fn test_frame_roundtrip_back_to_back() {
+    let mut codec = DIDFrameCodec::new();
+    let mut buf = BytesMut::new();
+    let first = frame("DATA,did://abc/,imapotato,127.0.0.1", "hello");
+    let second = frame("#DATA,did://abc/ar/get,imapotato,127.0.0.1", "");
+
+    codec.encode(first.clone(), &mut buf).unwrap();
+    codec.encode(second.clone(), &mut buf).unwrap();
+    assert!(buf.starts_with(b"DATA,did://abc/,imapotato,127.0.0.1,5\n\nhello"));
+
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(first));
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(second));
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+}
+
+#[test]
+fn test_frame_partial_reads() {
+    let mut codec = DIDFrameCodec::new();
+    let mut wire = BytesMut::new();
+    let mut buf = BytesMut::new();
+    let expected = frame("DATA,did://abc/,imapotato,127.0.0.1", "some body");
+
+    codec.encode(expected.clone(), &mut wire).unwrap();
+    for byte in &wire[..wire.len() - 1] {
+        buf.extend_from_slice(&[*byte]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    }
+
+    buf.extend_from_slice(&wire[wire.len() - 1..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(expected));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_frame_rejects_bad_size() {
+    let mut codec = DIDFrameCodec::new();
+    let mut buf = BytesMut::from("DATA,did://abc/,imapotato,127.0.0.1,x\n\n");
+
+    assert!(codec.decode(&mut buf).is_err());
+}
+
+#[test]
+fn test_binary_request_roundtrip() {
+    let mut wire = b"DATA,did://abc/,imapotato,127.0.0.1,4\n\n".to_vec();
+    wire.extend_from_slice(&[0x00, 0xff, b'\n', 0xc3]);
+
+    let req = DIDRequest::from_bytes(&wire).unwrap();
+
+    assert_eq!(req.body.as_bytes(), &[0x00, 0xff, b'\n', 0xc3]);
+    assert!(req.body.as_str().is_err());
+    assert_eq!(req.to_bytes(), wire);
+}
+
+#[test]
+fn test_malformed_request_names_field() {
+    let cases: [(&[u8], ReqField); 6] = [
+        (b"WHAT,did://abc/,imapotato,127.0.0.1,0\n\n", ReqField::Verb),
+        (b"DATA,not a url,imapotato,127.0.0.1,0\n\n", ReqField::Url),
+        (b"DATA,did://abc/,,127.0.0.1,0\n\n", ReqField::Did),
+        (b"DATA,did://abc/,imapotato,127.0.0.300,0\n\n", ReqField::Ip),
+        (b"DATA,did://abc/,imapotato,127.0.0.1,-1\n\n", ReqField::Size),
+        (b"DATA,did://abc/,imapotato,127.0.0.1,0\nx", ReqField::Separator)
+    ];
+
+    for (wire, field) in cases {
+        let err = DIDRequest::from_bytes(wire).err().unwrap();
+
+        assert_eq!(err.kind, DIDErrorKind::MalformedRequest(field));
+    }
+}
+
+#[test]
+fn test_reduced_header_roundtrip() {
+    let wire = b"PREFLIGHT,imapotato,127.0.0.1,9\n\nECDH_ONLY";
+    let req = DIDRequest::from_bytes(wire).unwrap();
+
+    assert!(matches!(req.header(), DIDHeader::Reduced { .. }));
+    assert!(req.url.is_none());
+    assert_eq!(req.to_bytes(), &wire[..]);
+}
+
+#[test]
+fn test_response_roundtrip() {
+    let ok = b"OK,DATA,did://abc/,imapotato,127.0.0.1,2\n\nhi";
+    let err = b"malformed_request:ip,PREFLIGHT,imapotato,127.0.0.1,0\n\n";
+
+    let res = DIDResponse::from_bytes(ok).unwrap();
+    assert!(res.is_ok());
+    assert_eq!(res.to_bytes().unwrap(), &ok[..]);
+
+    let res = DIDResponse::from_bytes(err).unwrap();
+    assert_eq!(
+        res.status,
+        DIDStatus::Err(DIDErrorKind::MalformedRequest(ReqField::Ip))
+    );
+    assert_eq!(res.to_bytes().unwrap(), &err[..]);
+
+    // Responses can't be serialized before their responder is set.
+    let req = data("/");
+    let mut res = DIDResponse::ok(&req, "hi");
+
+    assert_eq!(res.to_bytes().unwrap_err().kind, DIDErrorKind::ServerError);
+    res.set_responder("imapotato", req.ip);
+    assert_eq!(res.to_bytes().unwrap(), &ok[..]);
+}
+
+#[test]
+fn test_ipv6_header() {
+    let wire = b"DATA,did://abc/,imapotato,[2001:db8::1],0\n\n";
+    let req = DIDRequest::from_bytes(wire).unwrap();
+
+    assert_eq!(req.ip, "2001:db8::1".parse::<std::net::IpAddr>().unwrap());
+    assert_eq!(req.to_bytes(), &wire[..]);
+
+    let bare = b"DATA,did://abc/,imapotato,2001:db8::1,0\n\n";
+    let err = DIDRequest::from_bytes(bare).err().unwrap();
+    assert_eq!(err.kind, DIDErrorKind::MalformedRequest(ReqField::Ip));
+}
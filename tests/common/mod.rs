@@ -0,0 +1,35 @@
+//! Fixtures shared by the integration tests, each of which only uses some of
+//! them.
+#![allow(dead_code)]
+
+use std::net::{IpAddr, Ipv4Addr};
+use proto_did::req::reqres::DIDRequest;
+
+pub const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Request sent by `did` from `LOCALHOST`.
+pub fn request_from(
+    did: &str,
+    verb: &str,
+    url: &str,
+    body: &str
+) -> DIDRequest {
+    DIDRequest::from_bytes(format!(
+        "{verb},{url},{did},{LOCALHOST},{}\n\n{body}", body.len()
+    ).as_bytes()).unwrap()
+}
+
+/// Request sent by `imapotato`, the DID of the test servers.
+pub fn request(verb: &str, url: &str, body: &str) -> DIDRequest {
+    request_from("imapotato", verb, url, body)
+}
+
+/// Empty `DATA` request sent by `did` to `path` on `did://abc`.
+pub fn data_from(did: &str, path: &str) -> DIDRequest {
+    request_from(did, "DATA", &format!("did://abc{path}"), "")
+}
+
+/// Empty `DATA` request sent by `imapotato` to `path` on `did://abc`.
+pub fn data(path: &str) -> DIDRequest {
+    data_from("imapotato", path)
+}
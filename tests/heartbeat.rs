@@ -0,0 +1,135 @@
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use tokio_util::codec::Framed;
+use proto_did::{
+    client::DIDClient,
+    error::DIDErrorKind,
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        verbs::ReqVerb},
+    DIDFrameCodec,
+    DIDServer,
+    Heartbeat};
+
+mod common;
+use common::{data, LOCALHOST};
+
+fn heartbeat() -> Heartbeat {
+    Heartbeat::new(Duration::from_millis(50), 2)
+}
+
+fn server() -> DIDServer {
+    let mut server = DIDServer::build();
+
+    server
+        .add_route(ReqVerb::Data, "/", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, "hello")
+        })
+        .add_route(ReqVerb::Data, "/slow", |req: DIDRequest| async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            DIDResponse::ok(&req, "slow")
+        });
+    server.config.set_heartbeat(Some(heartbeat()));
+    server
+}
+
+#[tokio::test]
+async fn test_silent_session_evicted() {
+    let handle = server().start_in_memory();
+    let io = handle.duplex().await.unwrap();
+    let mut stream = Framed::new(io, DIDFrameCodec::new());
+
+    let ping = DIDRequest::ping("imapotato", LOCALHOST);
+
+    stream.send(ping.to_frame()).await.unwrap();
+
+    let frame = stream.next().await.unwrap().unwrap();
+    let res = DIDResponse::try_from(frame).unwrap();
+
+    assert!(res.is_ok());
+    assert_eq!(res.verb, ReqVerb::Ping);
+    assert_eq!(handle.session_count(), 1);
+
+    // Nothing more is sent: the server closes the connection.
+    let end = tokio::time::timeout(Duration::from_secs(2), stream.next()).await;
+
+    assert!(end.unwrap().is_none());
+    assert_eq!(handle.session_count(), 0);
+}
+
+#[tokio::test]
+async fn test_pending_requests_keep_session() {
+    let handle = server().start_in_memory();
+    let io = handle.duplex().await.unwrap();
+    let mut stream = Framed::new(io, DIDFrameCodec::new());
+    let mut req = data("/slow");
+
+    // Both requests outlast the heartbeat timeout without any ping.
+    stream.send(req.to_frame()).await.unwrap();
+    req.id = Some(1);
+    stream.send(req.to_frame()).await.unwrap();
+
+    for _ in 0..2 {
+        let frame = stream.next().await.unwrap().unwrap();
+
+        assert!(DIDResponse::try_from(frame).unwrap().is_ok());
+    }
+    assert_eq!(handle.session_count(), 1);
+}
+
+#[tokio::test]
+async fn test_heartbeat_keeps_session() {
+    let handle = server().start_in_memory();
+    let client = DIDClient::from_transport(handle.duplex().await.unwrap());
+
+    client.start_heartbeat("imapotato", LOCALHOST, heartbeat());
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    assert!(client.send(data("/")).await.unwrap().is_ok());
+    assert_eq!(handle.session_count(), 1);
+}
+
+#[tokio::test]
+async fn test_missed_heartbeats_close_client() {
+    // A zero interval is raised to `Heartbeat::MIN_INTERVAL`.
+    for heartbeat in [heartbeat(), Heartbeat::new(Duration::ZERO, 2)] {
+        // Nothing answers on the other end.
+        let (io, _peer) = tokio::io::duplex(1024);
+        let client = DIDClient::from_transport(io);
+
+        client.start_heartbeat("imapotato", LOCALHOST, heartbeat);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        let err = client.send(data("/")).await.unwrap_err();
+
+        assert_eq!(err.kind, DIDErrorKind::TcpConnectionClosed);
+    }
+}
+
+#[tokio::test]
+async fn test_missed_heartbeat_forgotten() {
+    let (io, peer) = tokio::io::duplex(1024);
+    let client = DIDClient::from_transport(io);
+
+    // Stand-in for a server ignoring ids, which loses the first ping and
+    // answers everything else.
+    tokio::spawn(async move {
+        let mut stream = Framed::new(peer, DIDFrameCodec::new());
+        let _ = stream.next().await;
+
+        while let Some(Ok(frame)) = stream.next().await {
+            let req = DIDRequest::try_from(frame).unwrap();
+            let mut res = DIDResponse::ok(&req, "");
+
+            res.id = None;
+            res.set_responder("imapotato", req.ip);
+            stream.send(res.to_frame().unwrap()).await.unwrap();
+        }
+    });
+
+    // Only one ping in a row is missed, the next pong answers the next ping.
+    client.start_heartbeat("imapotato", LOCALHOST, heartbeat());
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    assert!(client.send(data("/")).await.unwrap().is_ok());
+}
@@ -0,0 +1,133 @@
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream};
+use proto_did::{
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        verbs::ReqVerb},
+    DIDServer};
+
+mod common;
+use common::request_from;
+
+fn server() -> DIDServer {
+    let mut server = DIDServer::build();
+
+    server
+        .set_port(0)
+        .add_route(ReqVerb::Data, "/echo", |req: DIDRequest| async move {
+            let body = format!("{} {}", req.did, req.body.as_str().unwrap());
+
+            DIDResponse::ok(&req, body)
+        });
+    server
+}
+
+#[tokio::test]
+async fn test_http_requests() {
+    let handle = server().start().unwrap();
+    let mut sock = TcpStream::connect(handle.local_addr()).await.unwrap();
+
+    // The body is only complete after the second write.
+    sock.write_all(
+        b"POST /echo HTTP/1.1\r\nDID: imapotato\r\nContent-Length: 11\r\n\r\nhello"
+    ).await.unwrap();
+    sock.write_all(b" world").await.unwrap();
+    sock.write_all(
+        b"GET /nope HTTP/1.1\r\nDID: imapotato\r\n\r\n\
+        GET /echo HTTP/1.1\r\nConnection: close\r\n\r\n"
+    ).await.unwrap();
+
+    let mut res = String::new();
+    sock.read_to_string(&mut res).await.unwrap();
+
+    let responses = res.split("HTTP/1.1 ").skip(1).collect::<Vec<&str>>();
+    assert_eq!(responses.len(), 3);
+    assert!(responses[0].starts_with("200 OK\r\n"));
+    assert!(responses[0].contains("DID-Status: OK\r\n"));
+    assert!(responses[0].ends_with("\r\n\r\nimapotato hello world"));
+    assert!(responses[1].starts_with("404 Not Found\r\n"));
+    assert!(responses[2].starts_with("400 Bad Request\r\n"));
+    assert!(responses[2].contains("DID-Status: malformed_request:did\r\n"));
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_protocol_switches() {
+    let mut server = server();
+
+    server.http_enabled = false;
+
+    let handle = server.start().unwrap();
+    let mut sock = TcpStream::connect(handle.local_addr()).await.unwrap();
+
+    sock.write_all(b"GET /echo HTTP/1.1\r\nDID: imapotato\r\n\r\n").await.unwrap();
+
+    let mut res = vec![];
+    sock.read_to_end(&mut res).await.unwrap();
+    assert!(res.is_empty());
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_did_upgrade() {
+    use proto_did::client::DIDClient;
+
+    // Local stand-in for a DNS DID reached over HTTP.
+    let handle = server().start().unwrap();
+    let client = DIDClient::connect_upgrade(handle.local_addr(), "dns.com")
+        .await
+        .unwrap();
+
+    for body in ["first", "second"] {
+        let req = request_from(
+            "imapotato2", "DATA", "did://dns:common/echo", body
+        );
+        let res = client.send(req).await.unwrap();
+
+        assert_eq!(res.body.as_str().unwrap(), format!("imapotato2 {body}"));
+        assert_eq!(res.did, "imapotato");
+    }
+
+    handle.shutdown().await.unwrap();
+
+    // Without DID support, the upgrade is refused.
+    let mut server = server();
+
+    server.did_enabled = false;
+
+    let handle = server.start().unwrap();
+    let err = DIDClient::connect_upgrade(handle.local_addr(), "dns.com").await;
+
+    assert!(err.is_err());
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_http_restrictions() {
+    let handle = server().start_in_memory();
+    let mut sock = handle.duplex().await.unwrap();
+
+    // HTTP requests don't bind their session to the DID header.
+    sock.write_all(
+        b"HEAD /echo HTTP/1.1\r\nDID: alice\r\n\r\n\
+        POST /echo HTTP/1.1\r\nDID: bob\r\nContent-Length: 2\r\n\r\nhi\
+        GET /ar/get HTTP/1.1\r\nDID: bob\r\nDID-Verb: #DATA\r\n\
+        Connection: close\r\n\r\n"
+    ).await.unwrap();
+
+    let mut res = String::new();
+    sock.read_to_string(&mut res).await.unwrap();
+
+    let responses = res.split("HTTP/1.1 ").skip(1).collect::<Vec<&str>>();
+    assert_eq!(responses.len(), 3);
+    // Headers only, describing the body of a GET.
+    assert!(responses[0].starts_with("200 OK\r\n"));
+    assert!(responses[0].contains("Content-Length: 6\r\n"));
+    assert!(responses[0].ends_with("\r\n\r\n"));
+    assert!(responses[1].ends_with("\r\n\r\nbob hi"));
+    assert!(responses[2].starts_with("400 Bad Request\r\n"));
+    assert!(responses[2].contains("DID-Status: malformed_request:verb\r\n"));
+}
@@ -1,5 +1,14 @@
-use std::{collections::HashMap, env};
-use proto_did::{cli::start_cli, DIDServer};
+use std::{collections::HashMap, env, time::Duration};
+use proto_did::{
+    cli::start_cli,
+    client::DIDClient,
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        verbs::ReqVerb},
+    DIDServer};
+
+mod common;
+use common::data;
 
 #[tokio::test]
 async fn test_server() {
@@ -9,9 +18,29 @@ async fn test_server() {
     if mode.is_some() && mode.unwrap() == "cli" {
         start_cli().await;
     } else {
-        DIDServer::build()
-            .set_port(5000)
-            .launch()
-            .await;
+        let handle = DIDServer::build()
+            .set_port(0)
+            .add_route(ReqVerb::Data, "/", |req: DIDRequest| async move {
+                DIDResponse::ok(&req, "hello")
+            })
+            .start()
+            .unwrap();
+        let addr = handle.local_addr();
+
+        assert_ne!(addr.port(), 0);
+
+        // An idle connection must not hold the shutdown back.
+        let client = DIDClient::connect(addr).await.unwrap();
+        let req = data("/");
+
+        assert!(client.send(req.clone()).await.unwrap().is_ok());
+
+        tokio::time::timeout(Duration::from_secs(5), handle.shutdown())
+            .await
+            .expect("shutdown timed out")
+            .unwrap();
+
+        assert!(client.send(req).await.is_err());
+        assert!(DIDClient::connect(addr).await.is_err());
     }
 }
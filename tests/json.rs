@@ -0,0 +1,77 @@
+use proto_did::{
+    did_route,
+    error::{DIDErrorKind, ReqField},
+    req::{
+        bodies::{KvMethod, KvOp, KvResponse, StorageStart, StorageStats},
+        json::Json,
+        reqres::{DIDResponse, DIDStatus},
+        router::Router},
+    routes};
+
+mod common;
+use common::request;
+
+#[did_route(DATA, "/kv")]
+async fn kv(op: Json<KvOp>) -> Json<KvResponse> {
+    Json(KvResponse {
+        for_target: op.target.clone(),
+        with_method: op.method,
+        value: None,
+        previous_value: op.0.new_value,
+        error: None
+    })
+}
+
+
+#[tokio::test]
+async fn test_json_route() {
+    let mut router = Router::new();
+
+    router.add_routes(routes![kv]);
+
+    let res = router.dispatch(request(
+        "DATA", "did://abc/kv",
+        r#"{"target": "a.b", "method": "set", "new_value": 3}"#
+    )).await;
+    let body = res.body.json::<KvResponse>().unwrap();
+    assert_eq!(body.with_method, KvMethod::Set);
+    assert_eq!(body.previous_value, Some(3.into()));
+
+    let malformed = DIDStatus::Err(DIDErrorKind::MalformedRequest(ReqField::Body));
+    let res = router.dispatch(request(
+        "DATA", "did://abc/kv",
+        r#"{"target": "a.b", "method": "get", "extra": true}"#
+    )).await;
+    assert_eq!(res.status, malformed);
+
+    let res = router.dispatch(
+        request("DATA", "did://abc/kv", r#"{"method": "get"}"#)
+    ).await;
+    assert_eq!(res.status, malformed);
+}
+
+#[test]
+fn test_json_response_failure() {
+    // Maps keyed by tuples don't serialize as JSON.
+    let value = std::collections::HashMap::from([((1, 2), 3)]);
+    let res = DIDResponse::json(&request("DATA", "did://abc/kv", ""), &value);
+
+    assert_eq!(res.status, DIDStatus::Err(DIDErrorKind::ServerError));
+}
+
+#[test]
+fn test_storage_stats() {
+    let never = serde_json::from_str::<StorageStats>(
+        r#"{"for_provider": "uuid", "storage_started_at": "never"}"#
+    ).unwrap();
+    assert!(matches!(never.storage_started_at, StorageStart::Never(_)));
+
+    let stats = serde_json::from_str::<StorageStats>(r#"{
+        "for_provider": "uuid",
+        "storage_started_at": 1700000000,
+        "storage_ended_at": -1,
+        "availability": 0.9
+    }"#).unwrap();
+    assert_eq!(stats.storage_started_at, StorageStart::At(1700000000));
+    assert_eq!(serde_json::to_value(&never).unwrap()["storage_started_at"], "never");
+}
@@ -0,0 +1,101 @@
+use proto_did::{
+    did_route,
+    error::DIDErrorKind,
+    req::{
+        reqres::{DIDRequest, DIDResponse, DIDStatus},
+        router::Router,
+        state::State},
+    routes};
+
+mod common;
+use common::request;
+
+#[derive(serde::Deserialize)]
+struct SetRequest {
+    payload: String
+}
+
+mod storage {
+    use super::*;
+
+    #[did_route(DATA, "/storage/<did>/<resource_id>/set")]
+    pub async fn set(
+        did: String,
+        resource_id: u32,
+        #[sender] sender: String,
+        #[body] body: SetRequest,
+        req: DIDRequest
+    ) -> DIDResponse {
+        DIDResponse::ok(
+            &req,
+            format!("{did} {resource_id} {sender} {}", body.payload)
+        )
+    }
+}
+
+#[did_route(WHERE?, "/")]
+async fn lookup(req: DIDRequest) -> DIDResponse {
+    DIDResponse::ok(&req, "found")
+}
+
+// Same name as the function generated by `did_route`.
+#[did_route(DATA, "/handler")]
+async fn handler(req: DIDRequest) -> DIDResponse {
+    DIDResponse::ok(&req, "handled")
+}
+
+struct Greeting {
+    text: &'static str
+}
+
+#[did_route(DATA, "/greet")]
+async fn greet(
+    #[sender] sender: String,
+    greeting: State<Greeting>,
+    req: DIDRequest
+) -> DIDResponse {
+    DIDResponse::ok(&req, format!("{} {sender}", greeting.text))
+}
+
+#[tokio::test]
+async fn test_did_route() {
+    let mut router = Router::new();
+
+    router.add_routes(routes![storage::set, lookup, handler]);
+
+    let res = router.dispatch(request(
+        "DATA", "did://abc/storage/xyz/42/set", r#"{"payload": "data"}"#
+    )).await;
+    assert_eq!(res.body.as_str().unwrap(), "xyz 42 imapotato data");
+
+    let res = router.dispatch(request(
+        "DATA", "did://abc/storage/xyz/42/set", r#"{"nope": 1}"#
+    )).await;
+    assert!(matches!(res.status, DIDStatus::Err(_)));
+
+    let res = router.dispatch(request("WHERE?", "did://abc?", "")).await;
+    assert_eq!(res.body.as_str().unwrap(), "found");
+
+    let res = router.dispatch(request("DATA", "did://abc/handler", "")).await;
+    assert_eq!(res.body.as_str().unwrap(), "handled");
+}
+
+#[tokio::test]
+async fn test_state() {
+    let mut router = Router::new();
+
+    router
+        .add_routes(routes![greet])
+        .manage(Greeting { text: "hello" });
+
+    let res = router.dispatch(request("DATA", "did://abc/greet", "")).await;
+    assert_eq!(res.body.as_str().unwrap(), "hello imapotato");
+
+    // A value that isn't managed is a server error, not a panic.
+    let mut router = Router::new();
+
+    router.add_routes(routes![greet]);
+
+    let res = router.dispatch(request("DATA", "did://abc/greet", "")).await;
+    assert_eq!(res.status, DIDStatus::Err(DIDErrorKind::ServerError));
+}
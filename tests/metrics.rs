@@ -0,0 +1,58 @@
+#![cfg(feature = "metrics")]
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use proto_did::{
+    client::DIDClient,
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        verbs::ReqVerb},
+    DIDServer};
+
+mod common;
+use common::{data, request, LOCALHOST};
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let mut server = DIDServer::build();
+
+    server
+        .add_route(ReqVerb::Data, "/", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, "hello")
+        })
+        .serve_metrics("/metrics");
+    server.metrics.ar_table_size.set(3);
+
+    let handle = server.start_in_memory();
+    let client = DIDClient::from_transport(handle.duplex().await.unwrap());
+
+    for req in [
+        data("/"),
+        request("#DATA", "did://abc/storage/stats", ""),
+        DIDRequest::ping("imapotato", LOCALHOST)
+    ] {
+        client.send(req).await.unwrap();
+    }
+
+    let mut http = handle.duplex().await.unwrap();
+    let mut res = String::new();
+
+    // Only the path of the target is compared.
+    http.write_all(
+        b"GET /metrics?x=1 HTTP/1.1\r\nHost: abc\r\n\
+        Connection: close\r\n\r\n"
+    ).await.unwrap();
+    http.read_to_string(&mut res).await.unwrap();
+
+    assert!(res.starts_with("HTTP/1.1 200 "));
+    assert!(res.contains("did_requests_total{kind=\"data\",status=\"OK\"} 1"));
+    assert!(res.contains(
+        "did_requests_total{kind=\"storage\",status=\"NOT_FOUND\"} 1"
+    ));
+    assert!(res.contains(
+        "did_requests_total{kind=\"heartbeat\",status=\"OK\"} 1"
+    ));
+    assert!(res.contains("did_connections_total 2"));
+    assert!(res.contains("did_ar_table_size 3"));
+    // The client and the scraping connections.
+    assert!(res.contains("did_sessions 2"));
+}
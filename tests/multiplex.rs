@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+use futures::{SinkExt, StreamExt};
+use tokio_util::codec::Framed;
+use proto_did::{
+    client::DIDClient,
+    error::{DIDErrorKind, ReqField},
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        verbs::ReqVerb},
+    DIDFrameCodec,
+    DIDServer};
+
+mod common;
+use common::data;
+
+fn server() -> DIDServer {
+    let mut server = DIDServer::build();
+
+    server
+        .add_route(ReqVerb::Data, "/slow", |req: DIDRequest| async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            DIDResponse::ok(&req, "slow")
+        })
+        .add_route(ReqVerb::Data, "/fast", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, "fast")
+        });
+    server
+}
+
+
+#[test]
+fn test_request_id_header() {
+    let req = DIDRequest::from_bytes(
+        b"DATA,did://abc/,imapotato,127.0.0.1,id=7,0\n\n"
+    ).unwrap();
+
+    assert_eq!(req.id, Some(7));
+    assert_eq!(
+        &req.to_bytes()[..],
+        b"DATA,did://abc/,imapotato,127.0.0.1,id=7,0\n\n"
+    );
+
+    let mut res = DIDResponse::ok(&req, "");
+
+    res.set_responder("imapotato", req.ip);
+
+    let res = DIDResponse::from_bytes(&res.to_bytes().unwrap()).unwrap();
+
+    assert_eq!(res.id, Some(7));
+    assert!(data("/").id.is_none());
+
+    let err = DIDRequest::from_bytes(
+        b"DATA,did://abc/,imapotato,127.0.0.1,id=x,0\n\n"
+    ).unwrap_err();
+
+    assert_eq!(err.kind, DIDErrorKind::MalformedRequest(ReqField::Id));
+}
+
+#[tokio::test]
+async fn test_tagged_responses() {
+    let handle = server().start_in_memory();
+    let io = handle.duplex().await.unwrap();
+    let mut stream = Framed::new(io, DIDFrameCodec::new());
+
+    for (id, path) in [(1, "/slow"), (2, "/fast")] {
+        let mut req = data(path);
+
+        req.id = Some(id);
+        stream.send(req.to_frame()).await.unwrap();
+    }
+
+    // The fast request is answered first, each response tagged.
+    for (id, body) in [(2, "fast"), (1, "slow")] {
+        let frame = stream.next().await.unwrap().unwrap();
+        let res = DIDResponse::try_from(frame).unwrap();
+
+        assert_eq!(res.id, Some(id));
+        assert_eq!(res.body.as_str().unwrap(), body);
+    }
+}
+
+#[tokio::test]
+async fn test_client_demultiplexing() {
+    let handle = server().start_in_memory();
+    let client = DIDClient::from_transport(handle.duplex().await.unwrap());
+    let clone = client.clone();
+    let started = Instant::now();
+    let (slow, other, fast) = tokio::join!(
+        client.send(data("/slow")),
+        clone.send(data("/slow")),
+        client.send(data("/fast"))
+    );
+
+    assert_eq!(slow.unwrap().body.as_str().unwrap(), "slow");
+    assert_eq!(other.unwrap().body.as_str().unwrap(), "slow");
+    assert_eq!(fast.unwrap().body.as_str().unwrap(), "fast");
+    assert!(started.elapsed() < Duration::from_millis(550));
+}
+
+#[tokio::test]
+async fn test_cancelled_requests() {
+    let (io, peer) = tokio::io::duplex(4096);
+    let client = DIDClient::from_transport(io);
+
+    // Stand-in for a server ignoring ids, which never answers the first
+    // request.
+    tokio::spawn(async move {
+        let mut stream = Framed::new(peer, DIDFrameCodec::new());
+        let _ = stream.next().await;
+        let frame = stream.next().await.unwrap().unwrap();
+        let req = DIDRequest::try_from(frame).unwrap();
+        let mut res = DIDResponse::ok(&req, "late");
+
+        res.id = None;
+        res.set_responder("imapotato", req.ip);
+        stream.send(res.to_frame().unwrap()).await.unwrap();
+        let _ = stream.next().await;
+    });
+
+    let cancelled = tokio::time::timeout(
+        Duration::from_millis(50),
+        client.send(data("/"))
+    ).await;
+
+    assert!(cancelled.is_err());
+
+    // The untagged response goes to the request still waiting for one.
+    let res = client.send(data("/")).await.unwrap();
+
+    assert_eq!(res.body.as_str().unwrap(), "late");
+}
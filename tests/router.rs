@@ -0,0 +1,180 @@
+use proto_did::{
+    error::DIDErrorKind,
+    req::{
+        reqres::{DIDRequest, DIDResponse, DIDStatus},
+        router::Router,
+        verbs::ReqVerb}};
+
+mod common;
+use common::{data_from, request};
+
+async fn hello(req: DIDRequest) -> DIDResponse {
+    DIDResponse::ok(&req, format!("hello {}", req.did))
+}
+
+#[tokio::test]
+async fn test_dispatch() {
+    let mut router = Router::new();
+
+    router
+        .add_route(ReqVerb::Data, "/hello", hello)
+        .extend_protocol("/hello", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, "internal")
+        });
+
+    let res = router.dispatch(request("DATA", "did://abc/hello", "")).await;
+    assert!(res.is_ok());
+    assert_eq!(res.body.as_str().unwrap(), "hello imapotato");
+
+    let res = router.dispatch(request("#DATA", "did://abc/hello", "")).await;
+    assert_eq!(res.body.as_str().unwrap(), "internal");
+
+    let res = router.dispatch(request("DATA", "did://abc/nope", "")).await;
+    assert_eq!(res.status, DIDStatus::NotFound);
+}
+
+#[tokio::test]
+async fn test_route_params() {
+    let mut router = Router::new();
+
+    router
+        .add_route(ReqVerb::Data, "/ar/score/<did>", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, req.param::<String>("did").unwrap())
+        })
+        .add_route(ReqVerb::Data, "/ar/score/me", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, "me")
+        })
+        .add_route(ReqVerb::Data, "/storage/<did>/<id>/set", |req: DIDRequest| async move {
+            let id = req.param::<u32>("id");
+            DIDResponse::ok(&req, format!("{:?}", id.ok()))
+        })
+        .add_route(ReqVerb::Data, "/storage/stats?<provider>", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, req.param::<String>("provider").unwrap())
+        })
+        .add_route(ReqVerb::Data, "/files/<path..>", |req: DIDRequest| async move {
+            let page = req.query_param::<u8>("page").unwrap_or(0);
+            DIDResponse::ok(&req, format!("{} {}", req.param::<String>("path").unwrap(), page))
+        });
+
+    let body = async |verb: &str, url: &str| {
+        let res = router.dispatch(request(verb, url, "")).await;
+        res.body.as_str().unwrap().to_string()
+    };
+
+    assert_eq!(body("DATA", "did://abc/ar/score/xyz").await, "xyz");
+    assert_eq!(body("DATA", "did://abc/ar/score/me").await, "me");
+    assert_eq!(body("DATA", "did://abc/storage/xyz/42/set").await, "Some(42)");
+    assert_eq!(body("DATA", "did://abc/storage/xyz/nope/set").await, "None");
+    assert_eq!(body("DATA", "did://abc/storage/stats?prov").await, "prov");
+    assert_eq!(body("DATA", "did://abc/files/a/b/c?page=3").await, "a/b/c 3");
+    assert_eq!(body("DATA", "did://abc/files").await, " 0");
+}
+
+#[tokio::test]
+async fn test_layers() {
+    use proto_did::{
+        error::{DIDError, DIDErrorKind},
+        req::layer::Next};
+
+    async fn auth(req: DIDRequest, next: Next) -> DIDResponse {
+        if req.did == "intruder" {
+            return DIDResponse::error(&req, &DIDError {
+                kind: DIDErrorKind::DidCheckFailure,
+                source: "auth".into(),
+                reason: "unknown sender".into()
+            });
+        }
+        next.run(req).await
+    }
+
+    let mut router = Router::new();
+
+    router
+        .add_route(ReqVerb::Data, "/hello", hello)
+        .add_route(ReqVerb::Data, "/other", hello)
+        .add_layer(auth)
+        .add_layer(|mut req: DIDRequest, next: Next| async move {
+            req.did = req.did.to_uppercase();
+            next.run(req).await
+        })
+        .add_route_layer(ReqVerb::Data, "/hello", |req: DIDRequest, next: Next| async move {
+            let mut res = next.run(req).await;
+            res.body = format!("{}!", res.body.as_str().unwrap()).into();
+            res
+        });
+
+    let res = router.dispatch(request("DATA", "did://abc/hello", "")).await;
+    assert_eq!(res.body.as_str().unwrap(), "hello IMAPOTATO!");
+
+    let res = router.dispatch(request("DATA", "did://abc/other", "")).await;
+    assert_eq!(res.body.as_str().unwrap(), "hello IMAPOTATO");
+
+    let res = router.dispatch(data_from("intruder", "/hello")).await;
+    assert!(!res.is_ok());
+}
+
+#[tokio::test]
+async fn test_protocol_namespace() {
+    let mut router = Router::new();
+
+    router.add_route(ReqVerb::Data, "/ar/get", hello);
+
+    let res = router.dispatch(request("DATA", "did://abc/ar/get", "")).await;
+    assert_eq!(res.body.as_str().unwrap(), "hello imapotato");
+
+    // Protocol endpoints aren't implemented until backed by a handler.
+    let res = router.dispatch(request("#DATA", "did://abc/ar/get", "")).await;
+    assert_eq!(res.status, DIDStatus::Err(DIDErrorKind::ServerError));
+
+    router.set_protocol_handler("/ar/score/<did>", |req: DIDRequest| async move {
+        DIDResponse::ok(&req, req.param::<String>("did").unwrap())
+    });
+
+    let req = request("#DATA", "did://abc/ar/score/xyz", "");
+    let res = router.dispatch(req).await;
+    assert_eq!(res.body.as_str().unwrap(), "xyz");
+
+    let res = router.dispatch(request("#DATA", "did://abc/nope", "")).await;
+    assert_eq!(res.status, DIDStatus::NotFound);
+
+    let unknown = std::panic::catch_unwind(|| {
+        Router::new().set_protocol_handler("/ar/other", hello);
+    });
+    assert!(unknown.is_err());
+
+    for path in ["/storage/<did>/extra", "/<ns>/get", "/<rest..>"] {
+        let clash = std::panic::catch_unwind(|| {
+            Router::new().extend_protocol(path, hello);
+        });
+        assert!(clash.is_err(), "{path}");
+    }
+
+    let refused = std::panic::catch_unwind(|| {
+        Router::new().add_route(ReqVerb::HashData, "/node/info", hello);
+    });
+    assert!(refused.is_err());
+}
+
+#[test]
+fn test_ambiguous_routes() {
+    // Less specific than another, or for another verb: accepted.
+    let router = || {
+        let mut router = Router::new();
+
+        router
+            .add_route(ReqVerb::Data, "/a/<id>", hello)
+            .add_route(ReqVerb::Data, "/<x>/<y>", hello)
+            .add_route(ReqVerb::Data, "/a/<rest..>", hello)
+            .add_route(ReqVerb::Where, "/<x>/b", hello);
+        router
+    };
+
+    router();
+
+    for path in ["/<x>/b", "/a/<name>", "/<x>/<y>/<rest..>"] {
+        let ambiguous = std::panic::catch_unwind(|| {
+            router().add_route(ReqVerb::Data, path, hello);
+        });
+        assert!(ambiguous.is_err(), "{path}");
+    }
+}
@@ -0,0 +1,228 @@
+use std::time::Duration;
+use tokio::net::TcpListener;
+use proto_did::{
+    client::DIDClient,
+    error::DIDErrorKind,
+    req::{
+        reqres::{DIDRequest, DIDResponse, DIDStatus},
+        verbs::ReqVerb},
+    DIDServer};
+
+mod common;
+use common::{data, data_from};
+
+#[tokio::test]
+async fn test_launch_with_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = DIDServer::build();
+
+    server.add_route(ReqVerb::Data, "/hello", |req: DIDRequest| async move {
+        DIDResponse::ok(&req, "hello")
+    });
+    tokio::spawn(async move { server.launch_with_listener(listener).await });
+
+    let client = DIDClient::connect(addr).await.unwrap();
+    let res = client.send(data("/hello")).await.unwrap();
+
+    assert_eq!(res.body.as_str().unwrap(), "hello");
+    assert_eq!(res.did, "imapotato");
+}
+
+async fn wait_sessions(handle: &proto_did::ServerHandle, count: usize) {
+    for _ in 0..100 {
+        if handle.session_count() == count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("expected {count} sessions, got {}", handle.session_count());
+}
+
+#[tokio::test]
+async fn test_session_cache() {
+    let mut server = DIDServer::build();
+
+    server
+        .set_port(0)
+        .add_route(ReqVerb::Data, "/hello", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, "hello")
+        });
+    server.config
+        .set_max_sessions(Some(1))
+        .set_session_ttl(Duration::from_millis(200));
+
+    let handle = server.start().unwrap();
+
+    // The least recently used session is closed to make room.
+    let first = DIDClient::connect(handle.local_addr()).await.unwrap();
+    assert!(first.send(data_from("first", "/hello")).await.unwrap().is_ok());
+
+    let second = DIDClient::connect(handle.local_addr()).await.unwrap();
+    assert!(second.send(data_from("second", "/hello")).await.unwrap().is_ok());
+    assert!(first.send(data_from("first", "/hello")).await.is_err());
+    wait_sessions(&handle, 1).await;
+
+    // Sessions without activity expire.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(second.send(data_from("second", "/hello")).await.is_err());
+    wait_sessions(&handle, 0).await;
+
+    // Sessions are dropped when their connection ends.
+    let third = DIDClient::connect(handle.local_addr()).await.unwrap();
+    assert!(third.send(data_from("third", "/hello")).await.unwrap().is_ok());
+    wait_sessions(&handle, 1).await;
+    drop(third);
+    wait_sessions(&handle, 0).await;
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_zero_session_ttl() {
+    let mut server = DIDServer::build();
+
+    server.config.set_session_ttl(Duration::ZERO);
+
+    // Sessions expire right away, while the server keeps running.
+    let handle = server.start_in_memory();
+    let running = Duration::from_millis(100);
+
+    assert!(tokio::time::timeout(running, handle.wait()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_session_did_binding() {
+    let mut server = DIDServer::build();
+
+    server.add_route(ReqVerb::Data, "/hello", |req: DIDRequest| async move {
+        DIDResponse::ok(&req, "hello")
+    });
+
+    let handle = server.start_in_memory();
+    let victim = DIDClient::from_transport(handle.duplex().await.unwrap());
+    let other = DIDClient::from_transport(handle.duplex().await.unwrap());
+
+    assert!(victim.send(data_from("alice", "/hello")).await.unwrap().is_ok());
+
+    // Claiming the DID of another session doesn't close it.
+    assert!(other.send(data_from("alice", "/hello")).await.unwrap().is_ok());
+    assert!(victim.send(data_from("alice", "/hello")).await.unwrap().is_ok());
+
+    // A session keeps the DID of its first request.
+    let res = victim.send(data_from("mallory", "/hello")).await.unwrap();
+
+    assert_eq!(res.status, DIDStatus::Err(DIDErrorKind::DidCheckFailure));
+    assert_eq!(handle.session_count(), 2);
+}
+
+#[tokio::test]
+async fn test_pipelined_requests() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::Framed;
+    use proto_did::DIDFrameCodec;
+
+    let mut server = DIDServer::build();
+
+    server
+        .set_port(0)
+        .add_route(ReqVerb::Data, "/echo/<n>", |req: DIDRequest| async move {
+            DIDResponse::ok(&req, req.param::<String>("n").unwrap())
+        });
+
+    let handle = server.start().unwrap();
+    let sock = tokio::net::TcpStream::connect(handle.local_addr()).await.unwrap();
+    let mut stream = Framed::new(sock, DIDFrameCodec::new());
+
+    // Every request is written before reading any response.
+    for n in 0..5 {
+        stream.feed(data(&format!("/echo/{n}")).to_frame()).await.unwrap();
+    }
+    stream.flush().await.unwrap();
+
+    for n in 0..5 {
+        let frame = stream.next().await.unwrap().unwrap();
+        let res = DIDResponse::try_from(frame).unwrap();
+
+        assert_eq!(res.body.as_str().unwrap(), n.to_string());
+    }
+
+    // A request from another IP than the session one is refused.
+    let mut req = data("/echo/9");
+
+    req.ip = "127.0.0.2".parse().unwrap();
+
+    stream.send(req.to_frame()).await.unwrap();
+    let res = DIDResponse::try_from(stream.next().await.unwrap().unwrap()).unwrap();
+    assert!(!res.is_ok());
+
+    drop(stream);
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_malformed_requests_answered() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::Framed;
+    use proto_did::{error::ReqField, DIDFrame, DIDFrameCodec};
+
+    let mut server = DIDServer::build();
+
+    server.add_route(ReqVerb::Data, "/echo/<n>", |req: DIDRequest| async move {
+        DIDResponse::ok(&req, req.param::<String>("n").unwrap())
+    });
+
+    let handle = server.start_in_memory();
+    let io = handle.duplex().await.unwrap();
+    let mut stream = Framed::new(io, DIDFrameCodec::new());
+
+    // A bad DID between untagged requests, then a bad IP in a tagged one.
+    for header in [
+        "DATA,did://abc/echo/0,imapotato,127.0.0.1",
+        "DATA,did://abc/echo/1,ima potato,127.0.0.1",
+        "DATA,did://abc/echo/2,imapotato,127.0.0.1",
+        "DATA,did://abc/echo/3,imapotato,999.1.1.1,id=5",
+        "DATA,did://abc/echo/4,imapotato,127.0.0.1,id=6"
+    ] {
+        let frame = DIDFrame { header: header.into(), body: Default::default() };
+
+        stream.feed(frame).await.unwrap();
+    }
+    stream.flush().await.unwrap();
+
+    let mut responses = vec![];
+
+    for _ in 0..5 {
+        let frame = stream.next().await.unwrap().unwrap();
+
+        responses.push(DIDResponse::try_from(frame).unwrap());
+    }
+
+    let malformed = |field| DIDStatus::Err(DIDErrorKind::MalformedRequest(field));
+
+    assert_eq!(responses[0].body.as_str().unwrap(), "0");
+    assert_eq!(responses[1].status, malformed(ReqField::Did));
+    assert_eq!(responses[2].body.as_str().unwrap(), "2");
+
+    let tagged = |id| responses.iter().find(|res| res.id == Some(id)).unwrap();
+
+    assert_eq!(tagged(5).status, malformed(ReqField::Ip));
+    assert_eq!(tagged(6).body.as_str().unwrap(), "4");
+
+    // A bad first request is answered before the connection is closed.
+    let io = handle.duplex().await.unwrap();
+    let mut stream = Framed::new(io, DIDFrameCodec::new());
+    let header = "DATA,did://abc/echo/0,imapotato,999.1.1.1";
+
+    stream.send(DIDFrame { header: header.into(), body: Default::default() })
+        .await
+        .unwrap();
+
+    let frame = stream.next().await.unwrap().unwrap();
+
+    assert_eq!(
+        DIDResponse::try_from(frame).unwrap().status,
+        malformed(ReqField::Ip)
+    );
+    assert!(stream.next().await.is_none());
+}
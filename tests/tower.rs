@@ -0,0 +1,100 @@
+#![cfg(feature = "tower")]
+
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio_util::codec::Framed;
+use tower::{Service, ServiceBuilder, ServiceExt};
+use proto_did::{
+    client::DIDClient,
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        router::Router,
+        verbs::ReqVerb},
+    DIDFrameCodec};
+
+mod common;
+use common::data;
+
+#[tokio::test]
+async fn test_router_service() {
+    let mut router = Router::new();
+
+    router.add_route(ReqVerb::Data, "/hello", |req: DIDRequest| async move {
+        DIDResponse::ok(&req, "hello")
+    });
+
+    let service = ServiceBuilder::new()
+        .timeout(Duration::from_secs(1))
+        .service(router);
+    let res = service.oneshot(data("/hello")).await.unwrap();
+
+    assert_eq!(res.body.as_str().unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn test_client_service() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (sock, _) = listener.accept().await.unwrap();
+        let mut stream = Framed::new(sock, DIDFrameCodec::new());
+
+        while let Some(Ok(frame)) = stream.next().await {
+            let req = DIDRequest::try_from(frame).unwrap();
+            let path = req.url.as_ref().unwrap().path.clone();
+
+            let mut res = DIDResponse::ok(&req, path);
+
+            res.set_responder("server", req.ip);
+            stream.send(res.to_frame().unwrap()).await.unwrap();
+        }
+    });
+
+    let client = DIDClient::connect(addr).await.unwrap();
+    let service = ServiceBuilder::new()
+        .timeout(Duration::from_secs(1))
+        .service(client.clone());
+
+    let res = service.oneshot(data("/first")).await.unwrap();
+    assert_eq!(res.body.as_str().unwrap(), "/first");
+
+    let res = client.send(data("/second")).await.unwrap();
+    assert_eq!(res.body.as_str().unwrap(), "/second");
+}
+
+#[tokio::test]
+async fn test_client_service_timeout() {
+    let (io, peer) = tokio::io::duplex(1024);
+
+    // Answers without ids, except `/lost` which is never answered.
+    tokio::spawn(async move {
+        let mut stream = Framed::new(peer, DIDFrameCodec::new());
+
+        while let Some(Ok(frame)) = stream.next().await {
+            let req = DIDRequest::try_from(frame).unwrap();
+            let path = req.url.as_ref().unwrap().path.clone();
+
+            if path == "/lost" {
+                continue;
+            }
+
+            let mut res = DIDResponse::ok(&req, path);
+
+            res.id = None;
+            res.set_responder("server", req.ip);
+            stream.send(res.to_frame().unwrap()).await.unwrap();
+        }
+    });
+
+    let mut service = ServiceBuilder::new()
+        .timeout(Duration::from_millis(50))
+        .service(DIDClient::from_transport(io));
+
+    let lost = service.ready().await.unwrap().call(data("/lost"));
+    assert!(lost.await.is_err());
+
+    let res = service.oneshot(data("/found")).await.unwrap();
+    assert_eq!(res.body.as_str().unwrap(), "/found");
+}
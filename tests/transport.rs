@@ -0,0 +1,49 @@
+use proto_did::{
+    client::DIDClient,
+    req::{
+        reqres::{DIDRequest, DIDResponse},
+        verbs::ReqVerb},
+    DIDServer};
+
+mod common;
+use common::data;
+
+fn hello_server() -> DIDServer {
+    let mut server = DIDServer::build();
+
+    server.add_route(ReqVerb::Data, "/hello", |req: DIDRequest| async move {
+        DIDResponse::ok(&req, "hello")
+    });
+    server
+}
+
+#[tokio::test]
+async fn test_in_memory() {
+    let handle = hello_server().start_in_memory();
+    let client = DIDClient::from_transport(handle.duplex().await.unwrap());
+    let res = client.send(data("/hello")).await.unwrap();
+
+    assert_eq!(res.body.as_str().unwrap(), "hello");
+    assert_eq!(res.ip.to_string(), "127.0.0.1");
+    assert!(handle.local_addrs().is_empty());
+
+    handle.shutdown().await.unwrap();
+    assert!(client.send(data("/hello")).await.is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket() {
+    let path = std::env::temp_dir()
+        .join(format!("proto-did-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    let handle = hello_server().start_with_listener(listener).unwrap();
+    let client = DIDClient::connect_unix(&path).await.unwrap();
+    let res = client.send(data("/hello")).await.unwrap();
+
+    assert_eq!(res.body.as_str().unwrap(), "hello");
+
+    handle.shutdown().await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
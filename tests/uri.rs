@@ -0,0 +1,59 @@
+use std::str::FromStr;
+use proto_did::req::uri::{AddressKind, DIDAddress, LookupMode};
+
+#[test]
+fn test_address_forms() {
+    let raw = DIDAddress::from_str("did://abcdef123/storage/stats?prov").unwrap();
+    assert_eq!(raw.kind(), AddressKind::Raw);
+    assert_eq!(raw.address(), Some("abcdef123"));
+    assert_eq!(raw.path, "/storage/stats");
+    assert_eq!(raw.query.as_deref(), Some("prov"));
+
+    let dns = DIDAddress::from_str("did://rift:google").unwrap();
+    assert_eq!(dns.kind(), AddressKind::Dns);
+    assert_eq!(dns.dns(), Some("rift"));
+    assert_eq!(dns.tld(), None);
+    assert_eq!(dns.common_name(), Some("google"));
+    assert_eq!(dns.dns_host().as_deref(), Some("rift.com"));
+    assert_eq!(dns.path, "/");
+
+    let tld = DIDAddress::from_str("did://somedns:io:github/ar/get").unwrap();
+    assert_eq!(tld.tld(), Some("io"));
+    assert_eq!(tld.dns_host().as_deref(), Some("somedns.io"));
+
+    let lookup = DIDAddress::from_str("did://abcdef123?").unwrap();
+    assert_eq!(lookup.lookup, Some(LookupMode::Target));
+    let lookup = DIDAddress::from_str("did://rift:google!").unwrap();
+    assert_eq!(lookup.lookup, Some(LookupMode::Storage));
+}
+
+#[test]
+fn test_address_display_and_errors() {
+    for addr in [
+        "did://abcdef123/",
+        "did://rift:google/x?y",
+        "did://somedns:io:github?",
+        "did://abcdef123!",
+        "did://[::1]/x",
+        "did://127.0.0.1/"
+    ] {
+        assert_eq!(DIDAddress::from_str(addr).unwrap().to_string(), addr);
+    }
+
+    for addr in [
+        "http://abc/",
+        "did://",
+        "did://a:b:c:d/",
+        "did://abc/x,y",
+        "did://abc?x",
+        "did://a b/",
+        "did://abc//x",
+        "did://abc/x/",
+        "did://abc/../x",
+        "did://abc/./x",
+        "did://[::1/",
+        "did://[nope]/"
+    ] {
+        assert!(DIDAddress::from_str(addr).is_err(), "{addr}");
+    }
+}